@@ -0,0 +1,206 @@
+use core::time::Duration;
+
+use aarch64_cpu::registers::{CNTFRQ_EL0, CNTPCT_EL0, CNTP_CTL_EL0, CNTP_CVAL_EL0};
+use small_std::sync::IRQSafeMutex;
+use tock_registers::interfaces::{Readable, Writeable};
+
+use crate::{
+    cpu::{core_id, NUM_CORES},
+    driver::DeviceDriver,
+    irq::{self, IRQHandler, IRQNumber},
+};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Called on every timer interrupt, after the ticks have been accounted.
+pub type CoreTimerIRQCallback = fn();
+
+/// Per-core state, the generic timer registers are banked for each core.
+struct CoreTimerInner {
+    tick_hz: u64,
+    /// Counter cycles between two ticks.
+    tick_interval: u64,
+    /// Counter value at which the next tick is due.
+    next_tick: u64,
+    /// Counter value at which the alarm is due, if any.
+    alarm: Option<u64>,
+    /// Number of ticks since the timer was initialized on this core.
+    ticks: u64,
+}
+
+/// The ARM generic timer of the cores, using the non-secure EL1 physical timer.
+pub struct CoreTimer {
+    inner: [IRQSafeMutex<CoreTimerInner>; NUM_CORES],
+    irq_callback: IRQSafeMutex<Option<CoreTimerIRQCallback>>,
+}
+
+#[inline(always)]
+fn counter() -> u64 {
+    CNTPCT_EL0.get()
+}
+
+#[inline(always)]
+fn frequency() -> u64 {
+    CNTFRQ_EL0.get()
+}
+
+impl CoreTimerInner {
+    const fn new(tick_hz: u64) -> Self {
+        Self {
+            tick_hz,
+            tick_interval: 0,
+            next_tick: 0,
+            alarm: None,
+            ticks: 0,
+        }
+    }
+
+    fn init(&mut self) -> Result<(), &'static str> {
+        if self.tick_hz == 0 || self.tick_hz > frequency() {
+            return Err("invalid tick frequency");
+        }
+
+        self.tick_interval = frequency() / self.tick_hz;
+        self.next_tick = counter() + self.tick_interval;
+        self.program_comparator();
+
+        CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+
+        Ok(())
+    }
+
+    /// Fire the timer interrupt at the nearest of the next tick and the alarm.
+    fn program_comparator(&self) {
+        let deadline = match self.alarm {
+            Some(alarm) => alarm.min(self.next_tick),
+            None => self.next_tick,
+        };
+        CNTP_CVAL_EL0.set(deadline);
+    }
+
+    fn set_alarm(&mut self, alarm: Option<u64>) {
+        self.alarm = alarm;
+        self.program_comparator();
+    }
+
+    /// Advance the tick deadline past the current counter value and clear an expired alarm.
+    fn advance(&mut self) {
+        let now = counter();
+
+        let elapsed = if now < self.next_tick {
+            0
+        } else {
+            (now - self.next_tick) / self.tick_interval + 1
+        };
+        self.next_tick += elapsed * self.tick_interval;
+        self.ticks += elapsed;
+
+        if self.alarm.is_some_and(|alarm| alarm <= now) {
+            self.alarm = None;
+        }
+        self.program_comparator();
+    }
+}
+
+impl CoreTimer {
+    pub const COMPATIBLE: &'static str = "ARM Generic Timer";
+
+    pub const fn new(tick_hz: u64) -> Self {
+        const fn inner(tick_hz: u64) -> IRQSafeMutex<CoreTimerInner> {
+            IRQSafeMutex::new(CoreTimerInner::new(tick_hz))
+        }
+
+        Self {
+            inner: [
+                inner(tick_hz),
+                inner(tick_hz),
+                inner(tick_hz),
+                inner(tick_hz),
+            ],
+            irq_callback: IRQSafeMutex::new(None),
+        }
+    }
+
+    /// State of the executing core's timer.
+    fn inner(&self) -> &IRQSafeMutex<CoreTimerInner> {
+        &self.inner[core_id()]
+    }
+
+    /// Start the periodic ticks on a secondary core and enable its timer IRQ.
+    ///
+    /// The boot core is initialized through the driver manager.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called once on each secondary core, after the IRQ manager has been registered
+    pub unsafe fn init_secondary_core(&self) -> Result<(), &'static str> {
+        self.inner().lock().unwrap().init()?;
+        irq::irq_manager()?.enable(&IRQNumber::CORE_TIMER)
+    }
+
+    /// Set the function called on every timer interrupt.
+    pub fn set_irq_callback(&self, callback: CoreTimerIRQCallback) {
+        let mut irq_callback = self.irq_callback.lock().unwrap();
+        *irq_callback = Some(callback);
+    }
+
+    /// Number of ticks since the timer was initialized on the executing core.
+    pub fn ticks(&self) -> u64 {
+        let inner = self.inner().lock().unwrap();
+        inner.ticks
+    }
+
+    /// Number of ticks per second.
+    pub fn tick_hz(&self) -> u64 {
+        let inner = self.inner().lock().unwrap();
+        inner.tick_hz
+    }
+
+    /// Time elapsed since the system counter started.
+    pub fn uptime(&self) -> Duration {
+        let nanos = counter() as u128 * NANOS_PER_SEC / frequency() as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Raise a timer interrupt once the uptime reaches `at`, in addition to the periodic ticks.
+    ///
+    /// The alarm is set on the executing core. Only one alarm is kept per core, setting a new one
+    /// (or `None`) replaces the previous alarm.
+    pub fn set_alarm(&self, at: Option<Duration>) {
+        let alarm = at.map(|at| (at.as_nanos() * frequency() as u128 / NANOS_PER_SEC) as u64);
+
+        let mut inner = self.inner().lock().unwrap();
+        inner.set_alarm(alarm);
+    }
+}
+
+impl DeviceDriver for CoreTimer {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        let mut inner = self.inner().lock().unwrap();
+        inner.init()
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &IRQNumber,
+    ) -> Result<(), &'static str> {
+        irq::register_and_enable(irq_number, Self::COMPATIBLE, self)
+    }
+}
+
+impl IRQHandler for CoreTimer {
+    fn handle(&self) -> Result<(), &'static str> {
+        self.inner().lock().unwrap().advance();
+
+        let callback = *self.irq_callback.lock().unwrap();
+        if let Some(callback) = callback {
+            callback();
+        }
+
+        Ok(())
+    }
+}
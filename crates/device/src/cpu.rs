@@ -0,0 +1,55 @@
+//! The Cortex-A53 cores of the BCM2837.
+
+use core::arch::asm;
+
+use aarch64_cpu::registers::MPIDR_EL1;
+use tock_registers::interfaces::Readable;
+
+/// Number of cores.
+pub const NUM_CORES: usize = 4;
+
+/// ID of the executing core.
+#[inline(always)]
+pub fn core_id() -> usize {
+    (MPIDR_EL1.get() & 0b11) as usize
+}
+
+/// Size in bytes of the smallest data cache line, from CTR_EL0.
+pub fn dcache_line_size() -> usize {
+    let ctr: u64;
+    // SAFETY: CTR_EL0 is a read-only identification register.
+    unsafe { asm!("mrs {}, CTR_EL0", out(reg) ctr, options(nomem, nostack)) };
+    4 << ((ctr >> 16) & 0xf)
+}
+
+/// Clean and invalidate the data cache lines of `start..start + len` to the point of coherency.
+///
+/// Memory shared with an observer outside of the caches, the VideoCore or a core with its MMU
+/// off, must go through this before the observer reads it and again before reading its writes.
+pub fn clean_invalidate_dcache_range(start: usize, len: usize) {
+    let line_size = dcache_line_size();
+    let mut line = start & !(line_size - 1);
+    while line < start + len {
+        // SAFETY: Cleaning and invalidating a line does not change the memory as seen by the core.
+        unsafe { asm!("dc civac, {}", in(reg) line, options(nostack)) };
+        line += line_size;
+    }
+    // SAFETY: A barrier has no other effect.
+    unsafe { asm!("dsb sy", options(nostack)) };
+}
+
+/// Make the instructions written to `start..start + len` visible to the instruction fetches.
+///
+/// Cleans the data cache lines to the point of unification and drops the whole instruction cache,
+/// which might hold stale lines of the previous content, e.g. of a reused page frame.
+pub fn sync_icache_range(start: usize, len: usize) {
+    let line_size = dcache_line_size();
+    let mut line = start & !(line_size - 1);
+    while line < start + len {
+        // SAFETY: Cleaning a line does not change the memory as seen by the core.
+        unsafe { asm!("dc cvau, {}", in(reg) line, options(nostack)) };
+        line += line_size;
+    }
+    // SAFETY: Only invalidates instruction cache lines.
+    unsafe { asm!("dsb ish", "ic iallu", "dsb ish", "isb", options(nostack)) };
+}
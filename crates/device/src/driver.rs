@@ -1,6 +1,8 @@
-use small_std::{println, sync::Mutex};
+use small_std::{println, sync::IRQSafeMutex};
 
-const NUM_DRIVERS: usize = 5;
+use crate::irq::IRQNumber;
+
+const NUM_DRIVERS: usize = 8;
 
 struct DriverManagerInner {
     next_index: usize,
@@ -17,6 +19,17 @@ pub trait DeviceDriver {
     unsafe fn init(&self) -> Result<(), &'static str> {
         Ok(())
     }
+
+    /// Called by the kernel to register and enable the device's IRQ handler.
+    ///
+    /// Drivers that declare an IRQ number in their descriptor must override this.
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &IRQNumber,
+    ) -> Result<(), &'static str> {
+        let _ = irq_number;
+        Err("driver does not handle IRQs")
+    }
 }
 
 pub type DeviceDriverPostInitCallback = unsafe fn() -> Result<(), &'static str>;
@@ -26,10 +39,11 @@ pub type DeviceDriverPostInitCallback = unsafe fn() -> Result<(), &'static str>;
 pub struct DeviceDriverDescriptor {
     device_driver: &'static (dyn DeviceDriver + Sync),
     post_init_callback: Option<DeviceDriverPostInitCallback>,
+    irq_number: Option<IRQNumber>,
 }
 
 pub struct DriverManager {
-    inner: Mutex<DriverManagerInner>,
+    inner: IRQSafeMutex<DriverManagerInner>,
 }
 
 static DRIVER_MANAGER: DriverManager = DriverManager::new();
@@ -47,10 +61,12 @@ impl DeviceDriverDescriptor {
     pub fn new(
         device_driver: &'static (dyn DeviceDriver + Sync),
         post_init_callback: Option<DeviceDriverPostInitCallback>,
+        irq_number: Option<IRQNumber>,
     ) -> Self {
         Self {
             device_driver,
             post_init_callback,
+            irq_number,
         }
     }
 }
@@ -68,7 +84,7 @@ impl Default for DriverManager {
 impl DriverManager {
     pub const fn new() -> Self {
         Self {
-            inner: Mutex::new(DriverManagerInner::new()),
+            inner: IRQSafeMutex::new(DriverManagerInner::new()),
         }
     }
 
@@ -81,40 +97,59 @@ impl DriverManager {
         inner.next_index += 1;
     }
 
-    /// Fully initialize all drivers.
+    /// Fully initialize all drivers and their IRQ handlers.
+    ///
+    /// IRQ handlers are registered only after every driver has been initialized, so the IRQ
+    /// manager can be brought up as a regular driver.
     ///
     /// # Safety
     ///
     /// - During init, drivers might do stuff with system-wide impact.
     pub unsafe fn init_drivers(&self) {
         let inner = self.inner.lock().unwrap();
-        inner
-            .descriptors
-            .iter()
-            .filter_map(|x| x.as_ref())
-            .for_each(|descriptor| {
-                // 1. Initialize driver
-                if let Err(e) = descriptor.device_driver.init() {
-                    panic!(
-                        "Error initializing driver: {}: {}",
-                        descriptor.device_driver.compatible(),
-                        e
-                    );
-                }
-
-                // 2. Call corresponding post init callback
-                let Some(callback) = &descriptor.post_init_callback else {
-                    return;
-                };
-
-                if let Err(e) = callback() {
-                    panic!(
-                        "Error during driver post-init callback: {}: {}",
-                        descriptor.device_driver.compatible(),
-                        e
-                    );
-                }
-            })
+        let descriptors = inner.descriptors.iter().filter_map(|x| x.as_ref());
+
+        descriptors.clone().for_each(|descriptor| {
+            // 1. Initialize driver
+            if let Err(e) = descriptor.device_driver.init() {
+                panic!(
+                    "Error initializing driver: {}: {}",
+                    descriptor.device_driver.compatible(),
+                    e
+                );
+            }
+
+            // 2. Call corresponding post init callback
+            let Some(callback) = &descriptor.post_init_callback else {
+                return;
+            };
+
+            if let Err(e) = callback() {
+                panic!(
+                    "Error during driver post-init callback: {}: {}",
+                    descriptor.device_driver.compatible(),
+                    e
+                );
+            }
+        });
+
+        // 3. Register and enable IRQ handlers
+        descriptors.for_each(|descriptor| {
+            let Some(irq_number) = &descriptor.irq_number else {
+                return;
+            };
+
+            if let Err(e) = descriptor
+                .device_driver
+                .register_and_enable_irq_handler(irq_number)
+            {
+                panic!(
+                    "Error during driver interrupt handler registration: {}: {}",
+                    descriptor.device_driver.compatible(),
+                    e
+                );
+            }
+        });
     }
 
     /// Enumerate all registered device drivers.
@@ -2,7 +2,7 @@ mod registers;
 mod utils;
 
 use registers::{Registers, GPFSEL1, GPPUD, GPPUDCLK0};
-use small_std::sync::Mutex;
+use small_std::sync::IRQSafeMutex;
 use tock_registers::interfaces::{ReadWriteable, Writeable};
 
 use crate::driver::DeviceDriver;
@@ -12,7 +12,7 @@ struct GPIOInner {
 }
 
 pub struct GPIO {
-    inner: Mutex<GPIOInner>,
+    inner: IRQSafeMutex<GPIOInner>,
 }
 
 impl GPIOInner {
@@ -55,7 +55,7 @@ impl GPIO {
     /// - The user must ensure to provide a correct MMIO start address
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: Mutex::new(GPIOInner::new(mmio_start_addr)),
+            inner: IRQSafeMutex::new(GPIOInner::new(mmio_start_addr)),
         }
     }
 
@@ -0,0 +1,249 @@
+mod registers;
+
+use registers::{
+    LocalRegisters, PeripheralRegisters, CORE_IRQ_SOURCE, GPU_INT_ROUTING, IRQ_BASIC_PENDING,
+};
+use small_std::{println, sync::IRQSafeMutex};
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+use crate::{
+    cpu::core_id,
+    driver::DeviceDriver,
+    irq::{IRQHandlerDescriptor, IRQManager, IRQNumber},
+};
+
+const NUM_LOCAL_IRQS: usize = 8;
+const NUM_PERIPHERAL_IRQS: usize = 64;
+const NUM_BASIC_IRQS: usize = 8;
+
+struct InterruptControllerInner {
+    peripheral_registers: PeripheralRegisters,
+    local_registers: LocalRegisters,
+}
+
+struct HandlerTable {
+    local: [Option<IRQHandlerDescriptor>; NUM_LOCAL_IRQS],
+    peripheral: [Option<IRQHandlerDescriptor>; NUM_PERIPHERAL_IRQS],
+    basic: [Option<IRQHandlerDescriptor>; NUM_BASIC_IRQS],
+}
+
+/// The BCM2837 peripheral interrupt controller together with the BCM2836 core-local one.
+pub struct InterruptController {
+    inner: IRQSafeMutex<InterruptControllerInner>,
+    handlers: IRQSafeMutex<HandlerTable>,
+}
+
+/// Snapshot of the pending IRQs of the current core.
+struct PendingIRQs {
+    local: u32,
+    peripheral: u64,
+    basic: u32,
+}
+
+/// Iterate the indices of the set bits in `bits`.
+fn set_bits(mut bits: u64) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let index = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        Some(index)
+    })
+}
+
+impl InterruptControllerInner {
+    const unsafe fn new(peripheral_mmio_start_addr: usize, local_mmio_start_addr: usize) -> Self {
+        Self {
+            peripheral_registers: PeripheralRegisters::new(peripheral_mmio_start_addr),
+            local_registers: LocalRegisters::new(local_mmio_start_addr),
+        }
+    }
+
+    fn init(&self) {
+        // start with all peripheral IRQs masked
+        self.peripheral_registers.DISABLE_IRQS_1.set(u32::MAX);
+        self.peripheral_registers.DISABLE_IRQS_2.set(u32::MAX);
+        self.peripheral_registers.DISABLE_BASIC_IRQS.set(u32::MAX);
+
+        // deliver the GPU IRQs to the current core
+        self.local_registers
+            .GPU_INT_ROUTING
+            .modify(GPU_INT_ROUTING::GPU_IRQ_ROUTING.val(core_id() as u32));
+    }
+
+    fn set_enabled(&self, irq_number: &IRQNumber, enabled: bool) -> Result<(), &'static str> {
+        let core = core_id();
+        let set_bit = |register: &tock_registers::registers::ReadWrite<u32>, bit: usize| {
+            let value = register.get();
+            register.set(if enabled {
+                value | (1 << bit)
+            } else {
+                value & !(1 << bit)
+            });
+        };
+
+        let registers = &self.peripheral_registers;
+        match *irq_number {
+            IRQNumber::Local(n @ 0..=3) => {
+                set_bit(&self.local_registers.CORE_TIMER_IRQCNTL[core], n)
+            }
+            IRQNumber::Local(n @ 4..=7) => {
+                set_bit(&self.local_registers.CORE_MAILBOX_IRQCNTL[core], n - 4)
+            }
+            IRQNumber::Peripheral(n @ 0..=31) if enabled => registers.ENABLE_IRQS_1.set(1 << n),
+            IRQNumber::Peripheral(n @ 0..=31) => registers.DISABLE_IRQS_1.set(1 << n),
+            IRQNumber::Peripheral(n @ 32..=63) if enabled => {
+                registers.ENABLE_IRQS_2.set(1 << (n - 32))
+            }
+            IRQNumber::Peripheral(n @ 32..=63) => registers.DISABLE_IRQS_2.set(1 << (n - 32)),
+            IRQNumber::Basic(n @ 0..=7) if enabled => registers.ENABLE_BASIC_IRQS.set(1 << n),
+            IRQNumber::Basic(n @ 0..=7) => registers.DISABLE_BASIC_IRQS.set(1 << n),
+            _ => return Err("IRQ number out of range"),
+        };
+
+        Ok(())
+    }
+
+    fn pending_irqs(&self) -> PendingIRQs {
+        let source = &self.local_registers.CORE_IRQ_SOURCE[core_id()];
+        let mut pending = PendingIRQs {
+            local: source.read(CORE_IRQ_SOURCE::LOCAL),
+            peripheral: 0,
+            basic: 0,
+        };
+
+        if source.is_set(CORE_IRQ_SOURCE::GPU) {
+            let registers = &self.peripheral_registers;
+            pending.basic = registers.IRQ_BASIC_PENDING.read(IRQ_BASIC_PENDING::BASIC);
+            pending.peripheral =
+                (registers.IRQ_PENDING_2.get() as u64) << 32 | registers.IRQ_PENDING_1.get() as u64;
+        }
+
+        pending
+    }
+}
+
+impl HandlerTable {
+    const fn new() -> Self {
+        Self {
+            local: [None; NUM_LOCAL_IRQS],
+            peripheral: [None; NUM_PERIPHERAL_IRQS],
+            basic: [None; NUM_BASIC_IRQS],
+        }
+    }
+
+    fn slot(&mut self, irq_number: &IRQNumber) -> Option<&mut Option<IRQHandlerDescriptor>> {
+        match *irq_number {
+            IRQNumber::Local(n) => self.local.get_mut(n),
+            IRQNumber::Peripheral(n) => self.peripheral.get_mut(n),
+            IRQNumber::Basic(n) => self.basic.get_mut(n),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &IRQHandlerDescriptor> {
+        self.local
+            .iter()
+            .chain(self.peripheral.iter())
+            .chain(self.basic.iter())
+            .filter_map(|x| x.as_ref())
+    }
+}
+
+impl PendingIRQs {
+    fn iter(&self) -> impl Iterator<Item = IRQNumber> {
+        let local = set_bits(self.local as u64).map(IRQNumber::Local);
+        let peripheral = set_bits(self.peripheral).map(IRQNumber::Peripheral);
+        let basic = set_bits(self.basic as u64).map(IRQNumber::Basic);
+        local.chain(peripheral).chain(basic)
+    }
+}
+
+impl InterruptController {
+    pub const COMPATIBLE: &'static str = "BCM Interrupt Controller";
+
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO start addresses of the peripheral
+    ///   interrupt controller and the ARM local peripherals
+    pub const unsafe fn new(
+        peripheral_mmio_start_addr: usize,
+        local_mmio_start_addr: usize,
+    ) -> Self {
+        Self {
+            inner: IRQSafeMutex::new(InterruptControllerInner::new(
+                peripheral_mmio_start_addr,
+                local_mmio_start_addr,
+            )),
+            handlers: IRQSafeMutex::new(HandlerTable::new()),
+        }
+    }
+
+    fn handler(&self, irq_number: &IRQNumber) -> Option<IRQHandlerDescriptor> {
+        let mut handlers = self.handlers.lock().unwrap();
+        handlers.slot(irq_number).and_then(|slot| *slot)
+    }
+}
+
+impl DeviceDriver for InterruptController {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        let inner = self.inner.lock().unwrap();
+        inner.init();
+
+        Ok(())
+    }
+}
+
+impl IRQManager for InterruptController {
+    fn register_handler(&self, descriptor: IRQHandlerDescriptor) -> Result<(), &'static str> {
+        let mut handlers = self.handlers.lock().unwrap();
+        let slot = handlers
+            .slot(&descriptor.number())
+            .ok_or("IRQ number out of range")?;
+        if slot.is_some() {
+            return Err("IRQ handler already registered");
+        }
+
+        *slot = Some(descriptor);
+        Ok(())
+    }
+
+    fn enable(&self, irq_number: &IRQNumber) -> Result<(), &'static str> {
+        let inner = self.inner.lock().unwrap();
+        inner.set_enabled(irq_number, true)
+    }
+
+    fn disable(&self, irq_number: &IRQNumber) -> Result<(), &'static str> {
+        let inner = self.inner.lock().unwrap();
+        inner.set_enabled(irq_number, false)
+    }
+
+    fn handle_pending_irqs(&self) -> Result<(), &'static str> {
+        let pending = self.inner.lock().unwrap().pending_irqs();
+
+        for irq_number in pending.iter() {
+            let descriptor = self
+                .handler(&irq_number)
+                .ok_or("no handler registered for pending IRQ")?;
+            descriptor.handler().handle()?;
+        }
+
+        Ok(())
+    }
+
+    fn print_handlers(&self) {
+        let handlers = self.handlers.lock().unwrap();
+        for (idx, descriptor) in handlers.iter().enumerate() {
+            println!(
+                "    {}. {}: {}",
+                idx + 1,
+                descriptor.number(),
+                descriptor.name()
+            );
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use tock_registers::{
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+use crate::common::MMIODerefWrapper;
+
+// Interrupt controller registers.
+//
+// Descriptions taken from
+// - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf
+// - https://datasheets.raspberrypi.com/bcm2836/bcm2836-peripherals.pdf
+register_bitfields! {
+    u32,
+
+    /// GPU interrupts routing
+    pub GPU_INT_ROUTING [
+        /// Core which receives the GPU FIQ
+        GPU_FIQ_ROUTING OFFSET(2) NUMBITS(2) [],
+        /// Core which receives the GPU IRQ
+        GPU_IRQ_ROUTING OFFSET(0) NUMBITS(2) []
+    ],
+    /// Core interrupt source
+    pub CORE_IRQ_SOURCE [
+        /// Local timer interrupt
+        LOCAL_TIMER OFFSET(11) NUMBITS(1) [],
+        /// GPU interrupt, the pending peripheral IRQs are in the peripheral interrupt controller
+        GPU OFFSET(8) NUMBITS(1) [],
+        /// Core timer and mailbox interrupts
+        LOCAL OFFSET(0) NUMBITS(8) []
+    ],
+    /// IRQ basic pending
+    pub IRQ_BASIC_PENDING [
+        /// One or more bits set in pending register 2
+        PENDING_2 OFFSET(9) NUMBITS(1) [],
+        /// One or more bits set in pending register 1
+        PENDING_1 OFFSET(8) NUMBITS(1) [],
+        /// ARM-specific basic interrupts
+        BASIC OFFSET(0) NUMBITS(8) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub PeripheralRegisterBlock {
+        (0x000 => _reserved1),
+        (0x200 => pub IRQ_BASIC_PENDING: ReadOnly<u32, IRQ_BASIC_PENDING::Register>),
+        (0x204 => pub IRQ_PENDING_1: ReadOnly<u32>),
+        (0x208 => pub IRQ_PENDING_2: ReadOnly<u32>),
+        (0x20c => pub FIQ_CONTROL: ReadWrite<u32>),
+        (0x210 => pub ENABLE_IRQS_1: WriteOnly<u32>),
+        (0x214 => pub ENABLE_IRQS_2: WriteOnly<u32>),
+        (0x218 => pub ENABLE_BASIC_IRQS: WriteOnly<u32>),
+        (0x21c => pub DISABLE_IRQS_1: WriteOnly<u32>),
+        (0x220 => pub DISABLE_IRQS_2: WriteOnly<u32>),
+        (0x224 => pub DISABLE_BASIC_IRQS: WriteOnly<u32>),
+        (0x228 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub LocalRegisterBlock {
+        (0x00 => _reserved1),
+        (0x0c => pub GPU_INT_ROUTING: ReadWrite<u32, GPU_INT_ROUTING::Register>),
+        (0x10 => _reserved2),
+        (0x40 => pub CORE_TIMER_IRQCNTL: [ReadWrite<u32>; 4]),
+        (0x50 => pub CORE_MAILBOX_IRQCNTL: [ReadWrite<u32>; 4]),
+        (0x60 => pub CORE_IRQ_SOURCE: [ReadOnly<u32, CORE_IRQ_SOURCE::Register>; 4]),
+        (0x70 => @END),
+    }
+}
+
+pub type PeripheralRegisters = MMIODerefWrapper<PeripheralRegisterBlock>;
+pub type LocalRegisters = MMIODerefWrapper<LocalRegisterBlock>;
@@ -0,0 +1,114 @@
+use small_std::sync::IRQSafeMutex;
+
+/// Interrupt numbers known to the interrupt controllers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IRQNumber {
+    /// Core-local interrupt source, e.g. `Local(1)` for the non-secure physical timer.
+    Local(usize),
+    /// Peripheral interrupt in IRQ bank 1 (0 - 31) or IRQ bank 2 (32 - 63).
+    Peripheral(usize),
+    /// ARM-specific basic interrupt, e.g. `Basic(0)` for the ARM timer.
+    Basic(usize),
+}
+
+impl IRQNumber {
+    /// Non-secure physical timer of the cores.
+    pub const CORE_TIMER: IRQNumber = IRQNumber::Local(1);
+    /// Auxiliary peripherals, including the mini UART.
+    pub const AUX: IRQNumber = IRQNumber::Peripheral(29);
+}
+
+impl core::fmt::Display for IRQNumber {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IRQNumber::Local(n) => write!(f, "Local({})", n),
+            IRQNumber::Peripheral(n) => write!(f, "Peripheral({})", n),
+            IRQNumber::Basic(n) => write!(f, "Basic({})", n),
+        }
+    }
+}
+
+/// Implemented by types that handle IRQs.
+pub trait IRQHandler {
+    /// Called when the corresponding interrupt is asserted.
+    fn handle(&self) -> Result<(), &'static str>;
+}
+
+/// A descriptor for IRQ handlers.
+#[derive(Clone, Copy)]
+pub struct IRQHandlerDescriptor {
+    number: IRQNumber,
+    name: &'static str,
+    handler: &'static (dyn IRQHandler + Sync),
+}
+
+impl IRQHandlerDescriptor {
+    pub fn new(
+        number: IRQNumber,
+        name: &'static str,
+        handler: &'static (dyn IRQHandler + Sync),
+    ) -> Self {
+        Self {
+            number,
+            name,
+            handler,
+        }
+    }
+
+    pub fn number(&self) -> IRQNumber {
+        self.number
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn handler(&self) -> &'static (dyn IRQHandler + Sync) {
+        self.handler
+    }
+}
+
+/// IRQ management functions, implemented by interrupt controllers.
+pub trait IRQManager {
+    /// Register a handler for the IRQ in the descriptor.
+    fn register_handler(&self, descriptor: IRQHandlerDescriptor) -> Result<(), &'static str>;
+
+    /// Unmask the IRQ at the interrupt controller.
+    fn enable(&self, irq_number: &IRQNumber) -> Result<(), &'static str>;
+
+    /// Mask the IRQ at the interrupt controller.
+    fn disable(&self, irq_number: &IRQNumber) -> Result<(), &'static str>;
+
+    /// Call the handlers of all pending IRQs.
+    ///
+    /// Fails if a pending IRQ has no handler or its handler fails.
+    fn handle_pending_irqs(&self) -> Result<(), &'static str>;
+
+    /// Print the registered handlers.
+    fn print_handlers(&self) {}
+}
+
+type IRQManagerRef = &'static (dyn IRQManager + Sync);
+
+static CURRENT_IRQ_MANAGER: IRQSafeMutex<Option<IRQManagerRef>> = IRQSafeMutex::new(None);
+
+pub fn register_irq_manager(new_manager: IRQManagerRef) {
+    let mut current_manager = CURRENT_IRQ_MANAGER.lock().unwrap();
+    *current_manager = Some(new_manager);
+}
+
+pub fn irq_manager() -> Result<IRQManagerRef, &'static str> {
+    let current_manager = CURRENT_IRQ_MANAGER.lock().unwrap();
+    current_manager.ok_or("no IRQ manager registered")
+}
+
+/// Register `handler` for `irq_number` with the current IRQ manager and enable the IRQ.
+pub fn register_and_enable(
+    irq_number: &IRQNumber,
+    name: &'static str,
+    handler: &'static (dyn IRQHandler + Sync),
+) -> Result<(), &'static str> {
+    let manager = irq_manager()?;
+    manager.register_handler(IRQHandlerDescriptor::new(*irq_number, name, handler))?;
+    manager.enable(irq_number)
+}
@@ -1,8 +1,12 @@
 #![no_std]
 
 pub mod common;
+pub mod core_timer;
+pub mod cpu;
 pub mod driver;
 pub mod gpio;
+pub mod interrupt_controller;
+pub mod irq;
 pub mod mailbox;
 pub mod mini_uart;
 pub mod watchdog;
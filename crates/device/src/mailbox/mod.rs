@@ -1,10 +1,10 @@
 mod macros;
 mod registers;
 
-use small_std::{print, sync::Mutex};
+use small_std::{print, sync::IRQSafeMutex};
 use tock_registers::interfaces::{Readable, Writeable};
 
-use crate::driver::DeviceDriver;
+use crate::{cpu, driver::DeviceDriver};
 use macros::{define_mailbox_message, try_enum_from_repr};
 use registers::{Registers, MAILBOX_STATUS};
 
@@ -13,11 +13,12 @@ struct MailboxInner {
 }
 
 pub struct Mailbox {
-    inner: Mutex<MailboxInner>,
+    inner: IRQSafeMutex<MailboxInner>,
 }
 
 impl MailboxInner {
     const CHANNEL_MASK: u32 = 0b1111;
+    const PROPERTY_CHANNEL: u8 = 8;
 
     const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
@@ -47,6 +48,8 @@ impl MailboxInner {
 
     fn write(&self, channel: u8, buffer_addr: *mut u32) {
         while !self.is_writable() {}
+        // The VideoCore takes a physical address. A higher half kernel mapping only sets bits
+        // above the lower 32, so truncating a virtual address yields the physical one.
         let message_addr = buffer_addr as u32 & !Self::CHANNEL_MASK;
         print!(""); // WTF??
         self.registers
@@ -54,9 +57,15 @@ impl MailboxInner {
             .set(message_addr | channel as u32);
     }
 
-    fn call(&self, buffer_addr: *mut u32) -> *mut u32 {
-        self.write(8, buffer_addr);
-        self.read(8)
+    fn call(&self, channel: u8, buffer_addr: *mut u32) -> *mut u32 {
+        // The VideoCore accesses the buffer in memory, around the data cache of the core
+        // SAFETY: Every message starts with its size in bytes.
+        let buffer_size = unsafe { buffer_addr.read_volatile() } as usize;
+        cpu::clean_invalidate_dcache_range(buffer_addr as usize, buffer_size);
+        self.write(channel, buffer_addr);
+        let response = self.read(channel);
+        cpu::clean_invalidate_dcache_range(buffer_addr as usize, buffer_size);
+        response
     }
 }
 
@@ -113,7 +122,8 @@ impl MailboxInner {
             end_tag: TagIdentifier::End,
         };
         // SAFETY: The buffer is properly aligned and has the correct size
-        self.call(unsafe { core::mem::transmute(core::ptr::addr_of_mut!(message)) });
+        let buffer = unsafe { core::mem::transmute(core::ptr::addr_of_mut!(message)) };
+        self.call(Self::PROPERTY_CHANNEL, buffer);
 
         use MailboxResponseCode as MRC;
         match MRC::try_from(message.code) {
@@ -143,7 +153,8 @@ impl MailboxInner {
             end_tag: TagIdentifier::End,
         };
         // SAFETY: The buffer is properly aligned and has the correct size
-        self.call(unsafe { core::mem::transmute(core::ptr::addr_of_mut!(message)) });
+        let buffer = unsafe { core::mem::transmute(core::ptr::addr_of_mut!(message)) };
+        self.call(Self::PROPERTY_CHANNEL, buffer);
 
         use MailboxResponseCode as MRC;
         match MRC::try_from(message.code) {
@@ -167,7 +178,7 @@ impl Mailbox {
     /// - The user must ensure to provide a correct MMIO start address
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: Mutex::new(MailboxInner::new(mmio_start_addr)),
+            inner: IRQSafeMutex::new(MailboxInner::new(mmio_start_addr)),
         }
     }
 
@@ -180,6 +191,24 @@ impl Mailbox {
         let inner = self.inner.lock().unwrap();
         inner.get_arm_memory()
     }
+
+    /// Send a raw property message buffer through `channel` and wait for the response.
+    ///
+    /// # Safety
+    ///
+    /// - The buffer must be 16-byte aligned and hold a well-formed message whose
+    ///   `buffer_size` covers the whole buffer.
+    pub unsafe fn call(&self, channel: u8, buffer: *mut u32) -> Result<(), &str> {
+        let inner = self.inner.lock().unwrap();
+        inner.call(channel, buffer);
+
+        use MailboxResponseCode as MRC;
+        match MRC::try_from(buffer.add(1).read_volatile()) {
+            Ok(MRC::Success) => Ok(()),
+            Ok(MRC::ParseFailed) => Err("Error parsing request buffer"),
+            _ => Err("Invalid response received"),
+        }
+    }
 }
 
 impl DeviceDriver for Mailbox {
@@ -1,25 +1,53 @@
 mod registers;
+mod ring_buffer;
 
 use core::fmt::Write;
 
+use aarch64_cpu::registers::DAIF;
 use registers::{Registers, AUXENB, AUX_MU_CNTL, AUX_MU_IER, AUX_MU_IIR, AUX_MU_LCR, AUX_MU_LSR};
-use small_std::{fmt::print::console, sync::Mutex};
+use ring_buffer::RingBuffer;
+use small_std::{fmt::print::console, sync::IRQSafeMutex};
 use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
 
-use crate::driver::DeviceDriver;
+use crate::{
+    driver::DeviceDriver,
+    irq::{self, IRQHandler, IRQNumber},
+};
+
+const RX_BUFFER_SIZE: usize = 1024;
+const TX_BUFFER_SIZE: usize = 4096;
+
+/// Called from the interrupt handler after bytes have been received.
+pub type MiniUartRxCallback = fn();
 
 struct MiniUartInner {
     registers: Registers,
+    /// Whether RX and TX go through the ring buffers, driven by the AUX interrupt.
+    is_async: bool,
+    rx_buffer: RingBuffer<RX_BUFFER_SIZE>,
+    tx_buffer: RingBuffer<TX_BUFFER_SIZE>,
 }
 
 pub struct MiniUart {
-    inner: Mutex<MiniUartInner>,
+    inner: IRQSafeMutex<MiniUartInner>,
+    /// The registers again, for the panic handler which must not wait for `inner`.
+    panic_registers: Registers,
+    rx_callback: IRQSafeMutex<Option<MiniUartRxCallback>>,
 }
 
+/// `core::fmt::Write` adapter that goes through the (possibly asynchronous) write path.
+struct Writer<'a>(&'a MiniUart);
+
+/// `core::fmt::Write` adapter for the panic handler, polling the transmitter directly.
+struct PanicWriter<'a>(&'a Registers);
+
 impl MiniUartInner {
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
             registers: Registers::new(mmio_start_addr),
+            is_async: false,
+            rx_buffer: RingBuffer::new(),
+            tx_buffer: RingBuffer::new(),
         }
     }
 
@@ -59,6 +87,22 @@ impl MiniUartInner {
             .modify(AUX_MU_CNTL::TRANSMITTER::Enable + AUX_MU_CNTL::RECEIVER::Enable);
     }
 
+    /// Switch to interrupt-driven RX and TX.
+    fn enable_async(&mut self) {
+        self.is_async = true;
+        self.registers.AUX_MU_IER.write(
+            AUX_MU_IER::LINE_STATUS_INTERRUPT::Enable + AUX_MU_IER::RECEIVE_INTERRUPT::Enable,
+        );
+    }
+
+    fn set_transmit_interrupt(&self, enabled: bool) {
+        self.registers.AUX_MU_IER.modify(if enabled {
+            AUX_MU_IER::TRANSMIT_INTERRUPT::Enable
+        } else {
+            AUX_MU_IER::TRANSMIT_INTERRUPT::Disable
+        });
+    }
+
     /// Check if data is available to read.
     fn is_readable(&self) -> bool {
         self.registers.AUX_MU_LSR.is_set(AUX_MU_LSR::DATA_READY)
@@ -81,21 +125,73 @@ impl MiniUartInner {
         self.registers.AUX_MU_IO.set(byte as u32)
     }
 
-    fn flush(&self) {
+    /// Read a byte without blocking, from the RX buffer in async mode.
+    fn try_read_byte(&mut self) -> Option<u8> {
+        if self.is_async {
+            return self.rx_buffer.pop();
+        }
+
+        self.is_readable().then(|| self.read_byte())
+    }
+
+    /// Queue a byte for the TX interrupt in async mode, or write it out directly otherwise.
+    fn enqueue_byte(&mut self, byte: u8) {
+        if !self.is_async {
+            self.write_byte(byte);
+            return;
+        }
+
+        // make room by pushing the oldest byte out ourselves, so the order is preserved
+        if let Err(byte) = self.tx_buffer.push(byte) {
+            if let Some(oldest) = self.tx_buffer.pop() {
+                self.write_byte(oldest);
+            }
+            let _ = self.tx_buffer.push(byte);
+        }
+        self.set_transmit_interrupt(true);
+    }
+
+    /// Move received bytes into the RX buffer and pending bytes out of the TX buffer.
+    ///
+    /// Returns whether any byte has been received.
+    fn handle_interrupt(&mut self) -> bool {
+        let mut received = false;
+        while self.is_readable() {
+            let byte = self.registers.AUX_MU_IO.get() as u8;
+            // drop the input if nobody is consuming it
+            let _ = self.rx_buffer.push(byte);
+            received = true;
+        }
+
+        while self.is_writable() {
+            match self.tx_buffer.pop() {
+                Some(byte) => self.registers.AUX_MU_IO.set(byte as u32),
+                None => {
+                    self.set_transmit_interrupt(false);
+                    break;
+                }
+            }
+        }
+
+        received
+    }
+
+    fn flush(&mut self) {
+        // Write out everything still buffered, interrupts might be masked
+        while let Some(byte) = self.tx_buffer.pop() {
+            self.write_byte(byte);
+        }
+        self.set_transmit_interrupt(false);
+
         // Spin until the transmitter is empty
         while !self.is_writable() {}
     }
-}
 
-impl core::fmt::Write for MiniUartInner {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for c in s.chars() {
-            if c == '\n' {
-                self.write_byte(b'\r');
-            }
-            self.write_byte(c as u8);
+    fn clear_rx(&mut self) {
+        self.rx_buffer.clear();
+        while self.is_readable() {
+            self.read_byte();
         }
-        Ok(())
     }
 }
 
@@ -107,11 +203,54 @@ impl MiniUart {
     /// - The user must ensure to provide a correct MMIO start address
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: Mutex::new(MiniUartInner::new(mmio_start_addr)),
+            inner: IRQSafeMutex::new(MiniUartInner::new(mmio_start_addr)),
+            panic_registers: Registers::new(mmio_start_addr),
+            rx_callback: IRQSafeMutex::new(None),
+        }
+    }
+
+    /// Set the function called whenever the interrupt handler has received bytes.
+    pub fn set_rx_callback(&self, callback: MiniUartRxCallback) {
+        let mut rx_callback = self.rx_callback.lock().unwrap();
+        *rx_callback = Some(callback);
+    }
+
+    fn write_byte(&self, byte: u8) {
+        let mut inner = self.inner.lock().unwrap();
+        if byte == b'\n' {
+            inner.enqueue_byte(b'\r');
+        }
+        inner.enqueue_byte(byte);
+    }
+}
+
+impl core::fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            self.0.write_byte(c as u8);
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Write for PanicWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                write_byte_polled(self.0, b'\r');
+            }
+            write_byte_polled(self.0, byte);
         }
+        Ok(())
     }
 }
 
+/// Write `byte` once the transmitter is empty, bypassing the TX buffer.
+fn write_byte_polled(registers: &Registers, byte: u8) {
+    while !registers.AUX_MU_LSR.is_set(AUX_MU_LSR::TRANSMITTER_EMPTY) {}
+    registers.AUX_MU_IO.set(byte as u32)
+}
+
 impl DeviceDriver for MiniUart {
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
@@ -123,39 +262,83 @@ impl DeviceDriver for MiniUart {
 
         Ok(())
     }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &IRQNumber,
+    ) -> Result<(), &'static str> {
+        irq::register_and_enable(irq_number, Self::COMPATIBLE, self)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.enable_async();
+
+        Ok(())
+    }
+}
+
+impl IRQHandler for MiniUart {
+    fn handle(&self) -> Result<(), &'static str> {
+        let received = self.inner.lock().unwrap().handle_interrupt();
+
+        // outside of the lock, the callback is likely to read the RX buffer
+        let callback = *self.rx_callback.lock().unwrap();
+        if let (true, Some(callback)) = (received, callback) {
+            callback();
+        }
+
+        Ok(())
+    }
 }
 
 impl console::Write for MiniUart {
     fn write_char(&self, c: char) {
-        let inner = self.inner.lock().unwrap();
-        if c == '\n' {
-            inner.write_byte(b'\r');
-        }
-        inner.write_byte(c as u8);
+        self.write_byte(c as u8);
     }
 
     fn write_fmt(&self, args: core::fmt::Arguments) -> core::fmt::Result {
-        let mut inner = self.inner.lock().unwrap();
-        inner.write_fmt(args)
+        Writer(self).write_fmt(args)
     }
 
     fn flush(&self) {
-        let inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
         inner.flush();
     }
+
+    fn panic_write_fmt(&self, args: core::fmt::Arguments) -> core::fmt::Result {
+        // write out what is still buffered first if possible, it is lost if the lock is taken
+        if let Some(mut inner) = self.inner.try_lock() {
+            inner.flush();
+        }
+        PanicWriter(&self.panic_registers).write_fmt(args)
+    }
 }
 
 impl console::Read for MiniUart {
     fn read_char(&self) -> char {
-        let inner = self.inner.lock().unwrap();
-        inner.read_byte() as char
+        loop {
+            if let Some(c) = self.try_read_char() {
+                return c;
+            }
+
+            // The RX buffer is only filled by the interrupt handler, poll the device directly if
+            // it cannot run.
+            if DAIF.is_set(DAIF::I) {
+                let inner = self.inner.lock().unwrap();
+                return inner.read_byte() as char;
+            }
+
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_read_char(&self) -> Option<char> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.try_read_byte().map(|byte| byte as char)
     }
 
     fn clear_rx(&self) {
-        let inner = self.inner.lock().unwrap();
-        while inner.is_readable() {
-            inner.read_byte();
-        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clear_rx();
     }
 }
 
@@ -22,12 +22,19 @@ register_bitfields! {
             Enable = 1,
         ]
     ],
+    // The datasheet swaps the receive and transmit bits, and marks bits 3:2 as "don't care" while
+    // they are in fact required for interrupts to be raised.
+    // See https://elinux.org/BCM2835_datasheet_errata#p12
     pub AUX_MU_IER [
-        RECEIVE_INTERRUPT OFFSET(1) NUMBITS(1) [
+        LINE_STATUS_INTERRUPT OFFSET(2) NUMBITS(2) [
+            Disable = 0b00,
+            Enable = 0b11,
+        ],
+        TRANSMIT_INTERRUPT OFFSET(1) NUMBITS(1) [
             Disable = 0,
             Enable = 1,
         ],
-        TRANSMIT_INTERRUPT OFFSET(0) NUMBITS(1) [
+        RECEIVE_INTERRUPT OFFSET(0) NUMBITS(1) [
             Disable = 0,
             Enable = 1,
         ]
@@ -37,6 +44,15 @@ register_bitfields! {
             ClearReceiveFifo = 0b01,
             ClearTransmitFifo = 0b10,
         ],
+        INTERRUPT_ID OFFSET(1) NUMBITS(2) [
+            NoInterrupts = 0b00,
+            TransmitHoldingEmpty = 0b01,
+            ReceiverHoldsByte = 0b10,
+        ],
+        INTERRUPT_PENDING OFFSET(0) NUMBITS(1) [
+            Pending = 0,
+            NotPending = 1,
+        ]
     ],
     pub AUX_MU_LCR [
         DATA_SIZE OFFSET(0) NUMBITS(1) [
@@ -1,7 +1,7 @@
 mod registers;
 
 use registers::Registers;
-use small_std::sync::Mutex;
+use small_std::sync::IRQSafeMutex;
 use tock_registers::interfaces::Writeable;
 
 use crate::driver::DeviceDriver;
@@ -13,7 +13,7 @@ struct WatchdogInner {
 }
 
 pub struct Watchdog {
-    inner: Mutex<WatchdogInner>,
+    inner: IRQSafeMutex<WatchdogInner>,
 }
 
 impl WatchdogInner {
@@ -42,7 +42,7 @@ impl Watchdog {
     /// - The user must ensure to provide a correct MMIO start address
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: Mutex::new(WatchdogInner::new(mmio_start_addr)),
+            inner: IRQSafeMutex::new(WatchdogInner::new(mmio_start_addr)),
         }
     }
 
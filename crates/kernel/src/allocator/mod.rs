@@ -0,0 +1,190 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ops::Range,
+    ptr::NonNull,
+};
+
+mod stats;
+
+use small_std::sync::IRQSafeMutex;
+
+use self::stats::AllocationSource;
+pub use self::stats::{AllocationTracker, HeapStats, SourceStats};
+use crate::memory::{self, SlabAllocator};
+
+extern "C" {
+    pub static _heap_start: usize;
+    pub static _heap_end_exclusive: usize;
+}
+
+/// Bounds of the arena of the bump allocator.
+pub fn heap_range() -> Range<usize> {
+    unsafe { &_heap_start as *const usize as usize..&_heap_end_exclusive as *const usize as usize }
+}
+
+/// A simple allocator that allocates memory from a fixed-size arena.
+///
+/// It serves the allocations made before the page allocator is set up.
+struct BumpAllocator {
+    current_offset: IRQSafeMutex<usize>,
+}
+
+impl BumpAllocator {
+    const fn new() -> Self {
+        Self {
+            current_offset: IRQSafeMutex::new(0),
+        }
+    }
+
+    fn compute_alloc_region(&self, current_offset: usize, layout: Layout) -> (usize, usize) {
+        let head = self.address_at(current_offset);
+        let size = layout.size();
+        let align = layout.align();
+
+        let start = unsafe { head.add(head.align_offset(align)) } as usize;
+        let end = start + size;
+
+        (start, end)
+    }
+
+    fn is_region_valid(&self, start: usize, end: usize) -> bool {
+        start >= self.heap_start() && end <= self.heap_end()
+    }
+
+    fn used(&self) -> usize {
+        *self.current_offset.lock().unwrap()
+    }
+
+    fn contains(&self, ptr: *mut u8) -> bool {
+        (self.heap_start()..self.heap_end()).contains(&(ptr as usize))
+    }
+
+    #[inline(always)]
+    fn heap_start(&self) -> usize {
+        heap_range().start
+    }
+
+    #[inline(always)]
+    fn heap_end(&self) -> usize {
+        heap_range().end
+    }
+
+    #[inline(always)]
+    fn address_at(&self, offset: usize) -> *mut u8 {
+        (self.heap_start() + offset) as *mut u8
+    }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // hold the lock until the offset is bumped, IRQ handlers might allocate in between
+        let mut current_offset = self.current_offset.lock().unwrap();
+        let (start, end) = self.compute_alloc_region(*current_offset, layout);
+        if !self.is_region_valid(start, end) {
+            return core::ptr::null_mut();
+        }
+
+        *current_offset = end - self.heap_start();
+        start as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        // This allocator never deallocates memory
+    }
+}
+
+/// Serves allocations from the slab allocator, or from the bump allocator until the page allocator
+/// is set up.
+struct KernelAllocator {
+    bump: BumpAllocator,
+    slab: IRQSafeMutex<SlabAllocator>,
+    stats: IRQSafeMutex<HeapStats>,
+    tracker: IRQSafeMutex<AllocationTracker>,
+}
+
+impl KernelAllocator {
+    const fn new() -> Self {
+        Self {
+            bump: BumpAllocator::new(),
+            slab: IRQSafeMutex::new(SlabAllocator::new()),
+            stats: IRQSafeMutex::new(HeapStats::new()),
+            tracker: IRQSafeMutex::new(AllocationTracker::new()),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let slab_ptr = self.slab.lock().unwrap().alloc(layout);
+        let (ptr, source) = match slab_ptr {
+            Some(ptr) => (ptr.as_ptr(), stats::slab_source(layout)),
+            None if !memory::is_initialized() => (self.bump.alloc(layout), AllocationSource::Early),
+            // out of memory, the early arena must not be drained in its place
+            None => (core::ptr::null_mut(), stats::slab_source(layout)),
+        };
+
+        if ptr.is_null() {
+            self.stats.lock().unwrap().record_failure(layout);
+            return ptr;
+        }
+        self.stats.lock().unwrap().record_alloc(layout, source);
+        if stats::is_tracking() {
+            let caller = stats::allocation_caller();
+            self.tracker
+                .lock()
+                .unwrap()
+                .insert(ptr as usize, layout.size(), caller);
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if stats::is_tracking() {
+            self.tracker.lock().unwrap().remove(ptr as usize);
+        }
+
+        if self.bump.contains(ptr) {
+            self.stats
+                .lock()
+                .unwrap()
+                .record_dealloc(layout, AllocationSource::Early);
+            return self.bump.dealloc(ptr, layout);
+        }
+        if let Some(ptr) = NonNull::new(ptr) {
+            self.stats
+                .lock()
+                .unwrap()
+                .record_dealloc(layout, stats::slab_source(layout));
+            self.slab.lock().unwrap().dealloc(ptr, layout);
+        }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator::new();
+
+/// Snapshot of the heap accounting.
+pub fn heap_stats() -> HeapStats {
+    *ALLOCATOR.stats.lock().unwrap()
+}
+
+/// Bytes of the bump allocator arena that have been handed out.
+pub fn heap_arena_used() -> usize {
+    ALLOCATOR.bump.used()
+}
+
+/// Start or stop recording the live allocations, starting over with an empty record.
+pub fn set_allocation_tracking(tracking: bool) {
+    ALLOCATOR.tracker.lock().unwrap().clear();
+    stats::set_tracking(tracking);
+}
+
+pub fn is_allocation_tracking() -> bool {
+    stats::is_tracking()
+}
+
+/// Snapshot of the live allocations recorded since tracking was enabled.
+pub fn allocation_tracker() -> AllocationTracker {
+    *ALLOCATOR.tracker.lock().unwrap()
+}
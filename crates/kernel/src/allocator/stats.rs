@@ -0,0 +1,188 @@
+//! Accounting of the kernel heap, and tracking of live allocations for leak hunting.
+
+use core::{
+    alloc::Layout,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use small_std::backtrace::Backtrace;
+
+use crate::{
+    memory::{self, NUM_SIZE_CLASSES},
+    symbols,
+};
+
+/// Number of live allocations recorded while tracking is enabled.
+const MAX_TRACKED_ALLOCATIONS: usize = 256;
+
+/// Symbols of the allocation machinery, skipped when looking for the caller of an allocation.
+const ALLOCATOR_SYMBOL_PREFIXES: [&str; 5] = [
+    "__rust_",
+    "__rg_",
+    "alloc::",
+    "<alloc::",
+    "<kernel::allocator::",
+];
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+/// Where an allocation has been served from.
+#[derive(Debug, Clone, Copy)]
+pub enum AllocationSource {
+    /// The object cache of the size class.
+    Object(usize),
+    /// Whole pages of the page allocator.
+    Pages,
+    /// The bump allocator arena, whose memory is never reused.
+    Early,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SourceStats {
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+impl SourceStats {
+    pub fn live(&self) -> usize {
+        self.allocations - self.frees
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes requested by the allocations that have not been freed.
+    pub bytes_in_use: usize,
+    pub peak_bytes_in_use: usize,
+    pub objects: [SourceStats; NUM_SIZE_CLASSES],
+    pub pages: SourceStats,
+    pub early: SourceStats,
+    pub failed_allocations: usize,
+    /// The layout of the last allocation that could not be served.
+    pub last_failed_layout: Option<Layout>,
+}
+
+impl HeapStats {
+    pub const fn new() -> Self {
+        const EMPTY: SourceStats = SourceStats {
+            allocations: 0,
+            frees: 0,
+        };
+        Self {
+            bytes_in_use: 0,
+            peak_bytes_in_use: 0,
+            objects: [EMPTY; NUM_SIZE_CLASSES],
+            pages: EMPTY,
+            early: EMPTY,
+            failed_allocations: 0,
+            last_failed_layout: None,
+        }
+    }
+
+    pub fn record_alloc(&mut self, layout: Layout, source: AllocationSource) {
+        self.source_mut(source).allocations += 1;
+        self.bytes_in_use += layout.size();
+        self.peak_bytes_in_use = self.peak_bytes_in_use.max(self.bytes_in_use);
+    }
+
+    pub fn record_dealloc(&mut self, layout: Layout, source: AllocationSource) {
+        self.source_mut(source).frees += 1;
+        self.bytes_in_use -= layout.size();
+    }
+
+    pub fn record_failure(&mut self, layout: Layout) {
+        self.failed_allocations += 1;
+        self.last_failed_layout = Some(layout);
+    }
+
+    fn source_mut(&mut self, source: AllocationSource) -> &mut SourceStats {
+        match source {
+            AllocationSource::Object(class) => &mut self.objects[class],
+            AllocationSource::Pages => &mut self.pages,
+            AllocationSource::Early => &mut self.early,
+        }
+    }
+}
+
+/// Where the allocation of `layout` is served from, unless it is in the bump allocator arena.
+pub fn slab_source(layout: Layout) -> AllocationSource {
+    memory::size_class(layout).map_or(AllocationSource::Pages, AllocationSource::Object)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiveAllocation {
+    pub address: usize,
+    pub size: usize,
+    /// Return address into the function that requested the allocation, 0 if unknown.
+    pub caller: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct AllocationTracker {
+    allocations: [Option<LiveAllocation>; MAX_TRACKED_ALLOCATIONS],
+    /// Allocations that did not fit in the table.
+    pub untracked: usize,
+}
+
+impl AllocationTracker {
+    pub const fn new() -> Self {
+        Self {
+            allocations: [None; MAX_TRACKED_ALLOCATIONS],
+            untracked: 0,
+        }
+    }
+
+    pub fn insert(&mut self, address: usize, size: usize, caller: usize) {
+        match self.allocations.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some(LiveAllocation {
+                    address,
+                    size,
+                    caller,
+                })
+            }
+            None => self.untracked += 1,
+        }
+    }
+
+    pub fn remove(&mut self, address: usize) {
+        let entry = self
+            .allocations
+            .iter_mut()
+            .find(|entry| entry.is_some_and(|allocation| allocation.address == address));
+        if let Some(entry) = entry {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.allocations = [None; MAX_TRACKED_ALLOCATIONS];
+        self.untracked = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LiveAllocation> {
+        self.allocations.iter().flatten()
+    }
+}
+
+pub fn is_tracking() -> bool {
+    TRACKING.load(Ordering::Relaxed)
+}
+
+pub fn set_tracking(tracking: bool) {
+    TRACKING.store(tracking, Ordering::Relaxed);
+}
+
+/// Return address into the first function up the stack that is not part of the allocator.
+pub fn allocation_caller() -> usize {
+    Backtrace::capture()
+        .frames()
+        .find(|&address| {
+            symbols::resolve(address).map_or(true, |symbol| {
+                !ALLOCATOR_SYMBOL_PREFIXES
+                    .iter()
+                    .any(|prefix| symbol.name.starts_with(prefix))
+            })
+        })
+        .unwrap_or(0)
+}
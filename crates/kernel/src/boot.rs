@@ -1,6 +1,10 @@
-use core::arch::global_asm;
+use core::{arch::global_asm, cell::UnsafeCell, ops::Range};
 
-use crate::exception;
+use crate::{
+    driver::{LOCAL_PERIPHERAL_PHYS_BASE, PERIPHERAL_PHYS_BASE},
+    exception,
+    memory::{mmu, phys_to_virt},
+};
 
 #[no_mangle]
 #[link_section = ".text._start_arguments"]
@@ -8,10 +12,42 @@ pub static BOOT_CORE_ID: u64 = 0;
 
 pub static mut DEVICETREE_START_ADDR: usize = 0;
 
+/// Bounds of the stack the boot core runs the kernel on.
+pub fn boot_core_stack_range() -> Range<usize> {
+    extern "Rust" {
+        static __boot_core_stack_start: UnsafeCell<()>;
+        static __boot_core_stack_end_exclusive: UnsafeCell<()>;
+    }
+
+    unsafe {
+        __boot_core_stack_start.get() as usize..__boot_core_stack_end_exclusive.get() as usize
+    }
+}
+
+/// Bounds of the loaded kernel image, from the code up to the end of `.bss`.
+pub fn kernel_image_range() -> Range<usize> {
+    extern "Rust" {
+        static __kernel_image_start: UnsafeCell<()>;
+        static __kernel_image_end_exclusive: UnsafeCell<()>;
+    }
+
+    unsafe { __kernel_image_start.get() as usize..__kernel_image_end_exclusive.get() as usize }
+}
+
 global_asm!(
     include_str!( "boot.s"),
     CONST_CURRENTEL_EL2 = const 0x8,
     CONST_CORE_ID_MASK = const 0b11,
+    CONST_PHYS_ADDR_MASK = const !mmu::KERNEL_VIRT_OFFSET,
+    CONST_DESC_TABLE = const mmu::DESC_TABLE,
+    CONST_NORMAL_MEMORY_END = const PERIPHERAL_PHYS_BASE,
+    CONST_KERNEL_NORMAL_BLOCK = const mmu::KERNEL_NORMAL_BLOCK,
+    CONST_KERNEL_DEVICE_BLOCK = const mmu::KERNEL_DEVICE_BLOCK,
+    CONST_LOCAL_PERIPHERAL_BLOCK = const LOCAL_PERIPHERAL_PHYS_BASE as u64 | mmu::KERNEL_DEVICE_BLOCK,
+    CONST_IDENTITY_NORMAL_BLOCK = const mmu::IDENTITY_NORMAL_BLOCK,
+    CONST_MAIR_EL1 = const mmu::MAIR_EL1_VALUE,
+    CONST_TCR_EL1 = const mmu::TCR_EL1_VALUE,
+    CONST_SCTLR_EL1 = const mmu::SCTLR_EL1_VALUE,
 );
 
 #[no_mangle]
@@ -19,12 +55,22 @@ pub unsafe extern "C" fn _start_rust(
     devicetree_start_addr: u64,
     phys_boot_core_stack_end_exclusive_addr: u64,
 ) -> ! {
+    // Still running at the physical address with the MMU off, the EL1 MMU is already
+    // configured by boot.s though, so EL1 is entered at the higher half.
     DEVICETREE_START_ADDR = devicetree_start_addr as usize;
 
     exception::transition_from_el2_to_el1(
-        // Since we are not going back to EL2, here we just use the same stack address directly.
-        phys_boot_core_stack_end_exclusive_addr,
+        // Since we are not going back to EL2, here we just use the same stack directly.
+        phys_to_virt(phys_boot_core_stack_end_exclusive_addr as usize) as u64,
         // Set exception return address to kernel_init()
-        crate::kernel_init as *const () as u64,
+        phys_to_virt(crate::kernel_init as *const () as usize) as u64,
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn _start_secondary_rust(core_stack_end_exclusive_addr: u64) -> ! {
+    exception::transition_from_el2_to_el1(
+        core_stack_end_exclusive_addr,
+        phys_to_virt(crate::secondary_kernel_init as *const () as usize) as u64,
     );
 }
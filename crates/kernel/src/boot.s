@@ -37,6 +37,15 @@ _start:
     b       .L_bss_init_loop
 
 .L_prepare_rust:
+    // Map the kernel and configure the MMU of EL1, which is entered at the higher half
+    bl      __create_page_tables
+    // BSS, the tables included, was written around the data cache, which must not hold stale
+    // lines of it once turned on
+    ADR_REL x1, __bss_start
+    ADR_REL x2, __bss_end_exclusive
+    bl      __invalidate_dcache_range
+    bl      __configure_el1_mmu
+
     // Set the stack pointer
     ADR_REL x1, __boot_core_stack_end_exclusive
     mov     sp, x1
@@ -52,4 +61,123 @@ _start:
 .type   _start, function
 .global _start
 
+// Entry of the secondary cores, once released from the spin table by `smp::start_secondary_cores`.
+_start_secondary:
+    mrs     x1, CurrentEL
+    cmp     x1, {CONST_CURRENTEL_EL2}
+    b.ne    .L_parking_loop
+
+    // The page tables are already filled by the boot core
+    bl      __configure_el1_mmu
+
+    // Look up the stack of this core, and pass its virtual address to Rust for EL1 in x0.
+    // Until then the MMU is off, so use its physical address.
+    mrs     x1, MPIDR_EL1
+    and     x1, x1, {CONST_CORE_ID_MASK}
+    ADR_REL x0, SECONDARY_CORE_STACK_ENDS
+    ldr     x0, [x0, x1, lsl #3]
+    and     x1, x0, {CONST_PHYS_ADDR_MASK}
+    mov     sp, x1
+
+    b _start_secondary_rust
+
+.size   _start_secondary, . - _start_secondary
+.type   _start_secondary, function
+.global _start_secondary
+
+// Point the PUD at \pud to the PMD at \pmd, and the PGD at \pgd to the PUD.
+.macro LINK_TABLES pgd, pud, pmd
+    ADR_REL x2, \pgd
+    ADR_REL x3, \pud
+    ADR_REL x4, \pmd
+    orr     x5, x3, {CONST_DESC_TABLE}
+    str     x5, [x2]
+    orr     x5, x4, {CONST_DESC_TABLE}
+    str     x5, [x3]
+.endm
+
+// Map the first GiB with 2 MiB blocks into the PMD at \pmd. The blocks below the peripherals get
+// the attributes in \normal, the others the attributes in \device.
+.macro FILL_PMD pmd, normal, device
+    ADR_REL x2, \pmd
+    ldr     x3, ={CONST_NORMAL_MEMORY_END}
+    ldr     x4, =\normal
+    ldr     x5, =\device
+    mov     x6, xzr
+    mov     x7, #1 << 30
+1:
+    cmp     x6, x3
+    csel    x8, x4, x5, lo
+    orr     x8, x8, x6
+    str     x8, [x2], #8
+    add     x6, x6, #1 << 21
+    cmp     x6, x7
+    b.lo    1b
+.endm
+
+// Fill the translation tables of the kernel and of the identity map, see memory/mmu.rs.
+// Runs with the MMU off, and only clobbers x2 - x8.
+__create_page_tables:
+    LINK_TABLES KERNEL_PGD, KERNEL_PUD, KERNEL_PMD
+    FILL_PMD    KERNEL_PMD, {CONST_KERNEL_NORMAL_BLOCK}, {CONST_KERNEL_DEVICE_BLOCK}
+    // The local peripherals fill the second GiB
+    ADR_REL x2, KERNEL_PUD
+    ldr     x3, ={CONST_LOCAL_PERIPHERAL_BLOCK}
+    str     x3, [x2, #8]
+
+    // The identity map only covers RAM, entries of 0 are invalid
+    LINK_TABLES IDENTITY_PGD, IDENTITY_PUD, IDENTITY_PMD
+    FILL_PMD    IDENTITY_PMD, {CONST_IDENTITY_NORMAL_BLOCK}, 0
+    ret
+
+.size   __create_page_tables, . - __create_page_tables
+.type   __create_page_tables, function
+
+// Invalidate the data cache lines of the range from x1 to x2 (exclusive).
+// Only clobbers x1 - x4.
+__invalidate_dcache_range:
+    // the line size is 4 << CTR_EL0.DminLine bytes
+    mrs     x3, CTR_EL0
+    ubfx    x3, x3, #16, #4
+    mov     x4, #4
+    lsl     x3, x4, x3
+    sub     x4, x3, #1
+    bic     x1, x1, x4
+1:
+    dc      ivac, x1
+    add     x1, x1, x3
+    cmp     x1, x2
+    b.lo    1b
+    dsb     sy
+    ret
+
+.size   __invalidate_dcache_range, . - __invalidate_dcache_range
+.type   __invalidate_dcache_range, function
+
+// Configure the translation of EL1 from EL2 and turn its MMU on, taking effect once in EL1.
+// Only clobbers x2.
+__configure_el1_mmu:
+    ldr     x2, ={CONST_MAIR_EL1}
+    msr     MAIR_EL1, x2
+    ldr     x2, ={CONST_TCR_EL1}
+    msr     TCR_EL1, x2
+    ADR_REL x2, IDENTITY_PGD
+    msr     TTBR0_EL1, x2
+    ADR_REL x2, KERNEL_PGD
+    msr     TTBR1_EL1, x2
+
+    // make the tables visible to the table walks, and drop stale translations
+    dsb     ish
+    tlbi    vmalle1
+    dsb     ish
+    isb
+
+    ldr     x2, ={CONST_SCTLR_EL1}
+    msr     SCTLR_EL1, x2
+    isb
+    ret
+
+.size   __configure_el1_mmu, . - __configure_el1_mmu
+.type   __configure_el1_mmu, function
+
 // vim: ft=asm
@@ -49,7 +49,7 @@ pub struct CpioEntry<'a> {
     pub content: &'a [u8],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct CpioArchive {
     addr: usize,
 }
@@ -107,7 +107,8 @@ impl CpioArchive {
         }
     }
 
-    pub fn files(&self) -> Iter {
+    /// The entries of the archive, which stays in memory as long as the kernel runs.
+    pub fn files(&self) -> Iter<'static> {
         Iter {
             current_addr: self.addr,
             marker: PhantomData,
@@ -1,9 +1,11 @@
 mod parser;
 mod spec;
 
+use core::ops::Range;
+
 use self::{
     parser::{DeviceTreeProperty, DeviceTreeToken, ParseTokenError},
-    spec::FdtHeader,
+    spec::{FdtHeader, FdtReserveEntry},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -67,13 +69,7 @@ impl DeviceTree {
     where
         F: FnMut(&str, Iter),
     {
-        let header = unsafe { &*(self.base_address as *const FdtHeader) };
-        if !header.is_valid() {
-            return Err(DeviceTreeError::InvalidMagic(header.magic()));
-        }
-        if header.version() != 17 {
-            return Err(DeviceTreeError::UnsupportedVersion(header.version()));
-        }
+        let header = self.header()?;
 
         let dt_struct_start = self.base_address + header.off_dt_struct() as usize;
         let dt_struct_end = dt_struct_start + header.size_dt_struct() as usize;
@@ -90,6 +86,39 @@ impl DeviceTree {
 
         Ok(())
     }
+
+    /// The memory occupied by the device tree blob itself.
+    pub fn memory_range(&self) -> Result<Range<usize>, DeviceTreeError> {
+        let header = self.header()?;
+        Ok(self.base_address..self.base_address + header.totalsize() as usize)
+    }
+
+    /// The regions listed in the memory reservation block, which must not be used as RAM.
+    pub fn memory_reservations(
+        &self,
+    ) -> Result<impl Iterator<Item = Range<usize>>, DeviceTreeError> {
+        let header = self.header()?;
+        let entries =
+            (self.base_address + header.off_mem_rsvmap() as usize) as *const FdtReserveEntry;
+
+        let reservations = (0..)
+            .map(move |i| unsafe { &*entries.add(i) })
+            .take_while(|entry| entry.address() != 0 || entry.size() != 0)
+            .map(|entry| entry.address() as usize..(entry.address() + entry.size()) as usize);
+        Ok(reservations)
+    }
+
+    fn header(&self) -> Result<&FdtHeader, DeviceTreeError> {
+        let header = unsafe { &*(self.base_address as *const FdtHeader) };
+        if !header.is_valid() {
+            return Err(DeviceTreeError::InvalidMagic(header.magic()));
+        }
+        if header.version() != 17 {
+            return Err(DeviceTreeError::UnsupportedVersion(header.version()));
+        }
+
+        Ok(header)
+    }
 }
 
 pub struct Iter {
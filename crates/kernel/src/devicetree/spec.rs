@@ -76,11 +76,23 @@ impl FdtHeader {
 
 #[repr(packed)]
 #[derive(Debug)]
-struct FdtReserveEntry {
+pub struct FdtReserveEntry {
     address: u64,
     size: u64,
 }
 
+impl FdtReserveEntry {
+    /// The physical address of the reserved region.
+    pub fn address(&self) -> u64 {
+        u64::from_be(self.address)
+    }
+
+    /// The size in bytes of the reserved region, the list ends with an entry of size 0.
+    pub fn size(&self) -> u64 {
+        u64::from_be(self.size)
+    }
+}
+
 #[repr(u32)]
 #[derive(Debug)]
 pub enum StructureBlockToken {
@@ -1,21 +1,39 @@
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use device::{
-    driver::DeviceDriverDescriptor, gpio::GPIO, mailbox::Mailbox, mini_uart::MiniUart,
-    watchdog::Watchdog,
+    core_timer::CoreTimer, driver::DeviceDriverDescriptor, gpio::GPIO,
+    interrupt_controller::InterruptController, irq::IRQNumber, mailbox::Mailbox,
+    mini_uart::MiniUart, watchdog::Watchdog,
 };
 use small_std::fmt::print::console;
 
-pub const PERIPHERAL_MMIO_BASE: usize = 0x3f000000;
+use crate::{memory, thread::WaitQueue, timer};
+
+/// Physical address of the peripherals, mapped as device memory.
+pub const PERIPHERAL_PHYS_BASE: usize = 0x3f000000;
+pub const LOCAL_PERIPHERAL_PHYS_BASE: usize = 0x40000000;
+
+pub const PERIPHERAL_MMIO_BASE: usize = memory::phys_to_virt(PERIPHERAL_PHYS_BASE);
 pub const GPIO_MMIO_BASE: usize = PERIPHERAL_MMIO_BASE + 0x00200000;
 pub const AUX_MMIO_BASE: usize = PERIPHERAL_MMIO_BASE + 0x00215000;
 pub const WATCHDOG_MMIO_BASE: usize = PERIPHERAL_MMIO_BASE + 0x00100000;
 pub const MAILBOX_MMIO_BASE: usize = PERIPHERAL_MMIO_BASE + 0x0000b880;
+pub const INTERRUPT_CONTROLLER_MMIO_BASE: usize = PERIPHERAL_MMIO_BASE + 0x0000b000;
+pub const LOCAL_PERIPHERAL_MMIO_BASE: usize = memory::phys_to_virt(LOCAL_PERIPHERAL_PHYS_BASE);
+
+/// Frequency of the periodic core timer tick.
+pub const TIMER_TICK_HZ: u64 = 100;
 
 static GPIO: GPIO = unsafe { GPIO::new(GPIO_MMIO_BASE) };
 static MINI_UART: MiniUart = unsafe { MiniUart::new(AUX_MMIO_BASE) };
 static WATCHDOG: Watchdog = unsafe { Watchdog::new(WATCHDOG_MMIO_BASE) };
 static MAILBOX: Mailbox = unsafe { Mailbox::new(MAILBOX_MMIO_BASE) };
+static CORE_TIMER: CoreTimer = CoreTimer::new(TIMER_TICK_HZ);
+static INTERRUPT_CONTROLLER: InterruptController =
+    unsafe { InterruptController::new(INTERRUPT_CONTROLLER_MMIO_BASE, LOCAL_PERIPHERAL_MMIO_BASE) };
+
+/// Threads waiting for input on the console.
+static CONSOLE_INPUT: WaitQueue = WaitQueue::new();
 
 pub unsafe fn register_drivers() -> Result<(), &'static str> {
     static INIT_DONE: AtomicBool = AtomicBool::new(false);
@@ -25,22 +43,42 @@ pub unsafe fn register_drivers() -> Result<(), &'static str> {
 
     let driver_manager = device::driver::driver_manager();
 
-    let gpio = DeviceDriverDescriptor::new(&GPIO, Some(gpio_post_init));
+    let interrupt_controller = DeviceDriverDescriptor::new(
+        &INTERRUPT_CONTROLLER,
+        Some(interrupt_controller_post_init),
+        None,
+    );
+    driver_manager.register_driver(interrupt_controller);
+
+    let gpio = DeviceDriverDescriptor::new(&GPIO, Some(gpio_post_init), None);
     driver_manager.register_driver(gpio);
 
-    let mini_uart = DeviceDriverDescriptor::new(&MINI_UART, Some(mini_uart_post_init));
+    let mini_uart =
+        DeviceDriverDescriptor::new(&MINI_UART, Some(mini_uart_post_init), Some(IRQNumber::AUX));
     driver_manager.register_driver(mini_uart);
 
-    let watchdog = DeviceDriverDescriptor::new(&WATCHDOG, None);
+    let watchdog = DeviceDriverDescriptor::new(&WATCHDOG, None, None);
     driver_manager.register_driver(watchdog);
 
-    let mailbox = DeviceDriverDescriptor::new(&MAILBOX, None);
+    let mailbox = DeviceDriverDescriptor::new(&MAILBOX, None, None);
     driver_manager.register_driver(mailbox);
 
+    let core_timer = DeviceDriverDescriptor::new(
+        &CORE_TIMER,
+        Some(core_timer_post_init),
+        Some(IRQNumber::CORE_TIMER),
+    );
+    driver_manager.register_driver(core_timer);
+
     INIT_DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
 
+fn interrupt_controller_post_init() -> Result<(), &'static str> {
+    device::irq::register_irq_manager(&INTERRUPT_CONTROLLER);
+    Ok(())
+}
+
 fn gpio_post_init() -> Result<(), &'static str> {
     GPIO.map_mini_uart();
     Ok(())
@@ -48,6 +86,12 @@ fn gpio_post_init() -> Result<(), &'static str> {
 
 fn mini_uart_post_init() -> Result<(), &'static str> {
     console::register_console(&MINI_UART);
+    MINI_UART.set_rx_callback(|| CONSOLE_INPUT.wake_all());
+    Ok(())
+}
+
+fn core_timer_post_init() -> Result<(), &'static str> {
+    CORE_TIMER.set_irq_callback(timer::handle_timer_irq);
     Ok(())
 }
 
@@ -58,3 +102,23 @@ pub fn watchdog() -> &'static Watchdog {
 pub fn mailbox() -> &'static Mailbox {
     &MAILBOX
 }
+
+pub fn core_timer() -> &'static CoreTimer {
+    &CORE_TIMER
+}
+
+/// Read a character from the console, blocking the running thread until one arrives.
+pub fn console_read_char() -> char {
+    console_read_char_interruptible(|| false).unwrap()
+}
+
+/// Like `console_read_char`, but give up with `None` as soon as `interrupted` holds once the
+/// thread is woken.
+pub fn console_read_char_interruptible(mut interrupted: impl FnMut() -> bool) -> Option<char> {
+    let mut c = None;
+    CONSOLE_INPUT.wait_until(|| {
+        c = console::console().try_read_char();
+        c.is_some() || interrupted()
+    });
+    c
+}
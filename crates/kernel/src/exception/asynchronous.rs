@@ -1,5 +1,10 @@
+use core::arch::asm;
+
 use aarch64_cpu::registers::DAIF;
-use tock_registers::interfaces::Readable;
+use tock_registers::interfaces::{Readable, Writeable};
+
+/// Bit of the IRQ mask in the `DAIFSet` and `DAIFClr` immediates.
+const DAIF_IRQ_BIT: u8 = 0b0010;
 
 trait DaifField {
     fn daif_field() -> tock_registers::fields::Field<u64, DAIF::Register>;
@@ -43,6 +48,61 @@ where
     DAIF.is_set(T::daif_field())
 }
 
+/// Unmask IRQs on the executing core.
+#[inline(always)]
+pub fn local_irq_unmask() {
+    unsafe { asm!("msr DAIFClr, {bit}", bit = const DAIF_IRQ_BIT, options(nostack)) };
+}
+
+/// Mask IRQs on the executing core.
+#[inline(always)]
+pub fn local_irq_mask() {
+    unsafe { asm!("msr DAIFSet, {bit}", bit = const DAIF_IRQ_BIT, options(nostack)) };
+}
+
+/// Saved DAIF state of the executing core, restored when dropped.
+#[must_use = "the previous state is restored as soon as the guard is dropped"]
+pub struct DaifGuard {
+    saved: u64,
+}
+
+impl Drop for DaifGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        DAIF.set(self.saved);
+    }
+}
+
+/// Mask IRQs until the returned guard is dropped.
+#[inline(always)]
+pub fn irq_masked_scope() -> DaifGuard {
+    let guard = DaifGuard { saved: DAIF.get() };
+    local_irq_mask();
+    guard
+}
+
+/// Unmask IRQs until the returned guard is dropped.
+#[inline(always)]
+pub fn irq_unmasked_scope() -> DaifGuard {
+    let guard = DaifGuard { saved: DAIF.get() };
+    local_irq_unmask();
+    guard
+}
+
+/// Run `f` with IRQs masked on the executing core, then restore the previous masking state.
+#[inline(always)]
+pub fn exec_with_irq_masked<T>(f: impl FnOnce() -> T) -> T {
+    let _guard = irq_masked_scope();
+    f()
+}
+
+/// Run `f` with IRQs unmasked on the executing core, then restore the previous masking state.
+#[inline(always)]
+pub fn exec_with_irq_unmasked<T>(f: impl FnOnce() -> T) -> T {
+    let _guard = irq_unmasked_scope();
+    f()
+}
+
 /// Print the AArch64 exceptions status
 pub fn print_state() {
     macro_rules! print_field {
@@ -1,40 +1,37 @@
+// The FP/SIMD registers are only touched if the running task owns the FPU, the kernel itself is
+// built without FP/SIMD support.
+.arch_extension fp
+.arch_extension simd
+
+// Size of `ExceptionContext`, see crates/kernel/src/exception/handler.rs
+.equ EXCEPTION_CONTEXT_SIZE, 16 * 51
+// Offsets into `ExceptionContext`
+.equ EXCEPTION_CONTEXT_SP_EL0, 16 * 17
+.equ EXCEPTION_CONTEXT_FP_SIMD, 16 * 18
+
+// Branch to `\label` if the FP/SIMD registers do not hold the state of the running task, i.e. if
+// EL0 accesses are trapped. Clobbers `\scratch`.
+.macro B_IF_FP_SIMD_TRAPPED scratch, label
+    mrs     \scratch, CPACR_EL1
+    ubfx    \scratch, \scratch, #20, #2    // CPACR_EL1.FPEN
+    cmp     \scratch, #0b11                 // FPEN::TrapNothing
+    b.ne    \label
+.endm
+
 // Call the function `\handler` after context saving.
+//
+// A vector table entry only has room for 32 instructions, so the bulk of the saving is done by
+// `__exception_save_context`.
 .macro CALL_WITH_CONTEXT handler
 __vector_\handler:
-    sub     sp, sp, #16 * 17
-
-    // Store all GPRs on the stack.
-    stp     x0,  x1,  [sp, #16 * 0]
-    stp     x2,  x3,  [sp, #16 * 1]
-    stp     x4,  x5,  [sp, #16 * 2]
-    stp     x6,  x7,  [sp, #16 * 3]
-    stp     x8,  x9,  [sp, #16 * 4]
-    stp     x10, x11, [sp, #16 * 5]
-    stp     x12, x13, [sp, #16 * 6]
-    stp     x14, x15, [sp, #16 * 7]
-    stp     x16, x17, [sp, #16 * 8]
-    stp     x18, x19, [sp, #16 * 9]
-    stp     x20, x21, [sp, #16 * 10]
-    stp     x22, x23, [sp, #16 * 11]
-    stp     x24, x25, [sp, #16 * 12]
-    stp     x26, x27, [sp, #16 * 13]
-    stp     x28, x29, [sp, #16 * 14]
-    // x30 is lr, it is stored below
-
-    mrs     x1, ELR_EL1     // exception link register
-    mrs     x2, SPSR_EL1    // saved program status register
-    mrs     x3, ESR_EL1     // exception syndrome register
-
-    stp     lr, x1, [sp, #16 * 15]
-    stp     x2, x3, [sp, #16 * 16]
+    sub     sp, sp, #EXCEPTION_CONTEXT_SIZE
 
-    // Pass `sp` as the first argument to the handler, so it can access the saved context.
-    mov     x0, sp
+    stp     x0, x1, [sp, #16 * 0]
+    str     lr,     [sp, #16 * 15]
 
-    bl      \handler
-
-    // restore the context after exception handling
-    b __exception_restore_context
+    adrp    x0, \handler
+    add     x0, x0, #:lo12:\handler
+    b       __exception_save_context
 
 .size   __vector_\handler, . - __vector_\handler
 .type   __vector_\handler, function
@@ -99,7 +96,63 @@ __exception_vector_start:
 
 .global __exception_vector_start
 
+// Save the rest of the context and call the handler at x0.
+// x0, x1 and lr have already been saved by `CALL_WITH_CONTEXT`.
+__exception_save_context:
+    // Store all GPRs on the stack.
+    stp     x2,  x3,  [sp, #16 * 1]
+    stp     x4,  x5,  [sp, #16 * 2]
+    stp     x6,  x7,  [sp, #16 * 3]
+    stp     x8,  x9,  [sp, #16 * 4]
+    stp     x10, x11, [sp, #16 * 5]
+    stp     x12, x13, [sp, #16 * 6]
+    stp     x14, x15, [sp, #16 * 7]
+    stp     x16, x17, [sp, #16 * 8]
+    stp     x18, x19, [sp, #16 * 9]
+    stp     x20, x21, [sp, #16 * 10]
+    stp     x22, x23, [sp, #16 * 11]
+    stp     x24, x25, [sp, #16 * 12]
+    stp     x26, x27, [sp, #16 * 13]
+    stp     x28, x29, [sp, #16 * 14]
+
+    mrs     x1, ELR_EL1     // exception link register
+    mrs     x2, SPSR_EL1    // saved program status register
+    mrs     x3, ESR_EL1     // exception syndrome register
+
+    str     x1,     [sp, #16 * 15 + 8]
+    stp     x2, x3, [sp, #16 * 16]
+
+    mrs     x1, SP_EL0
+    mrs     x2, TPIDR_EL0
+    stp     x1, x2, [sp, #EXCEPTION_CONTEXT_SP_EL0]
+
+    // Lazily saved, only if the running task has used the FPU.
+    mov     x19, x0
+    B_IF_FP_SIMD_TRAPPED x1, 1f
+    add     x1, sp, #EXCEPTION_CONTEXT_FP_SIMD
+    bl      __fp_simd_save
+1:
+    // Pass `sp` as the first argument to the handler, so it can access the saved context.
+    mov     x0, sp
+
+    blr     x19
+
+    // restore the context after exception handling
+    b __exception_restore_context
+
+.size   __exception_save_context, . - __exception_save_context
+.type   __exception_save_context, function
+
 __exception_restore_context:
+    // The handler might have given the FPU to the task, see `handle_fp_simd_trap`.
+    B_IF_FP_SIMD_TRAPPED x1, 1f
+    add     x1, sp, #EXCEPTION_CONTEXT_FP_SIMD
+    bl      __fp_simd_restore
+1:
+    ldp     x19, x20, [sp, #EXCEPTION_CONTEXT_SP_EL0]
+    msr     SP_EL0,    x19
+    msr     TPIDR_EL0, x20
+
     // top 32 bits of SPSR_EL1 are reserved, so we can just not load it
     ldr     w19,     [sp, #16 * 16]
     ldp     lr, x20, [sp, #16 * 15]
@@ -123,9 +176,62 @@ __exception_restore_context:
     ldp     x26, x27, [sp, #16 * 13]
     ldp     x28, x29, [sp, #16 * 14]
 
-    add     sp, sp, #16 * 17
+    add     sp, sp, #EXCEPTION_CONTEXT_SIZE
 
     eret
 
 .size   __exception_restore_context, . - __exception_restore_context
 .type   __exception_restore_context, function
+.global __exception_restore_context
+
+// Save FPCR, FPSR and q0 - q31 to the `FpSimdState` at x1.
+__fp_simd_save:
+    mrs     x2, FPCR
+    mrs     x3, FPSR
+    stp     x2,  x3,  [x1, #16 * 0]
+    stp     q0,  q1,  [x1, #16 * 1]
+    stp     q2,  q3,  [x1, #16 * 3]
+    stp     q4,  q5,  [x1, #16 * 5]
+    stp     q6,  q7,  [x1, #16 * 7]
+    stp     q8,  q9,  [x1, #16 * 9]
+    stp     q10, q11, [x1, #16 * 11]
+    stp     q12, q13, [x1, #16 * 13]
+    stp     q14, q15, [x1, #16 * 15]
+    stp     q16, q17, [x1, #16 * 17]
+    stp     q18, q19, [x1, #16 * 19]
+    stp     q20, q21, [x1, #16 * 21]
+    stp     q22, q23, [x1, #16 * 23]
+    stp     q24, q25, [x1, #16 * 25]
+    stp     q26, q27, [x1, #16 * 27]
+    stp     q28, q29, [x1, #16 * 29]
+    stp     q30, q31, [x1, #16 * 31]
+    ret
+
+.size   __fp_simd_save, . - __fp_simd_save
+.type   __fp_simd_save, function
+
+// Restore FPCR, FPSR and q0 - q31 from the `FpSimdState` at x1.
+__fp_simd_restore:
+    ldp     x2,  x3,  [x1, #16 * 0]
+    msr     FPCR, x2
+    msr     FPSR, x3
+    ldp     q0,  q1,  [x1, #16 * 1]
+    ldp     q2,  q3,  [x1, #16 * 3]
+    ldp     q4,  q5,  [x1, #16 * 5]
+    ldp     q6,  q7,  [x1, #16 * 7]
+    ldp     q8,  q9,  [x1, #16 * 9]
+    ldp     q10, q11, [x1, #16 * 11]
+    ldp     q12, q13, [x1, #16 * 13]
+    ldp     q14, q15, [x1, #16 * 15]
+    ldp     q16, q17, [x1, #16 * 17]
+    ldp     q18, q19, [x1, #16 * 19]
+    ldp     q20, q21, [x1, #16 * 21]
+    ldp     q22, q23, [x1, #16 * 23]
+    ldp     q24, q25, [x1, #16 * 25]
+    ldp     q26, q27, [x1, #16 * 27]
+    ldp     q28, q29, [x1, #16 * 29]
+    ldp     q30, q31, [x1, #16 * 31]
+    ret
+
+.size   __fp_simd_restore, . - __fp_simd_restore
+.type   __fp_simd_restore, function
@@ -0,0 +1,46 @@
+//! Lazy FP/SIMD context handling.
+//!
+//! Tasks start with their FP/SIMD accesses trapped. The first access gives the task the FPU with a
+//! clean state, and from then on the exception entry and exit code saves and restores the FP/SIMD
+//! registers along with the rest of the `ExceptionContext`.
+
+use aarch64_cpu::registers::CPACR_EL1;
+use tock_registers::interfaces::{ReadWriteable, Readable};
+
+/// FP/SIMD registers of a task, see `__fp_simd_save` in exception.s.
+#[repr(C)]
+#[derive(Default, Clone)]
+pub struct FpSimdState {
+    fpcr: u64,
+    fpsr: u64,
+    q: [u128; 32],
+}
+
+/// Whether the FP/SIMD registers hold the state of the running task.
+pub fn is_enabled() -> bool {
+    CPACR_EL1.matches_all(CPACR_EL1::FPEN::TrapNothing)
+}
+
+/// Give the running task the FPU.
+pub fn enable() {
+    CPACR_EL1.modify(CPACR_EL1::FPEN::TrapNothing);
+}
+
+/// Trap FP/SIMD accesses of the running task, until it first uses the FPU.
+pub fn disable() {
+    CPACR_EL1.modify(CPACR_EL1::FPEN::TrapEl0);
+}
+
+impl core::fmt::Display for FpSimdState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "FPCR: {:#010x}  FPSR: {:#010x}", self.fpcr, self.fpsr)?;
+
+        writeln!(f, "FP/SIMD registers:")?;
+        for (i, reg) in self.q.iter().enumerate() {
+            let separator = if i % 2 == 0 { "  " } else { "\n" };
+            write!(f, "      q{:<2}: {:#034x}{}", i, reg, separator)?;
+        }
+
+        Ok(())
+    }
+}
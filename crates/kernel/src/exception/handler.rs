@@ -4,36 +4,162 @@ use aarch64_cpu::{
     asm::barrier,
     registers::{ESR_EL1, FAR_EL1, SPSR_EL1, VBAR_EL1},
 };
+use device::irq;
+use small_std::{backtrace::Backtrace, println};
 use tock_registers::{
-    interfaces::{Readable, Writeable},
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields,
     registers::InMemoryRegister,
 };
 
+use super::{
+    fp_simd::{self, FpSimdState},
+    task_queue,
+};
+use crate::{memory::Access, process, symbols, syscall, thread};
+
 global_asm!(include_str!("exception.s"));
 
+register_bitfields! {u64,
+    /// ISS encoding for Data Aborts and Instruction Aborts, the latter only use some of the fields.
+    ABORT_ISS [
+        /// Instruction Syndrome Valid, whether SAS, SSE, SRT, SF and AR hold valid values.
+        ISV OFFSET(24) NUMBITS(1) [],
+        /// Syndrome Access Size.
+        SAS OFFSET(22) NUMBITS(2) [
+            Byte = 0b00,
+            Halfword = 0b01,
+            Word = 0b10,
+            Doubleword = 0b11
+        ],
+        /// Syndrome Register Transfer, the register number of the faulting load or store.
+        SRT OFFSET(16) NUMBITS(5) [],
+        /// Sixty Four bit register, whether the transfer register is 64-bit wide.
+        SF OFFSET(15) NUMBITS(1) [],
+        /// FAR not Valid.
+        FnV OFFSET(10) NUMBITS(1) [],
+        /// Fault on the stage 2 translation of a stage 1 translation table walk.
+        S1PTW OFFSET(7) NUMBITS(1) [],
+        /// Write not Read.
+        WnR OFFSET(6) NUMBITS(1) [],
+        /// Data or Instruction Fault Status Code.
+        FSC OFFSET(0) NUMBITS(6) []
+    ]
+}
+
 #[repr(transparent)]
 struct SpsrEL1(InMemoryRegister<u64, SPSR_EL1::Register>);
+#[repr(transparent)]
 struct EsrEL1(InMemoryRegister<u64, ESR_EL1::Register>);
 
 #[repr(C)]
-struct ExceptionContext {
+pub struct ExceptionContext {
     /// general perpose registers
-    gpr: [u64; 30],
+    pub gpr: [u64; 30],
 
     /// link register (x30)
-    lr: u64,
+    pub lr: u64,
 
     /// exception link register
-    elr_el1: u64,
+    pub elr_el1: u64,
 
     /// saved program status register
     spsr_el1: SpsrEL1,
 
     /// exception syndrome register
     esr_el1: EsrEL1,
+
+    /// user stack pointer
+    pub sp_el0: u64,
+
+    /// user thread ID register
+    pub tpidr_el0: u64,
+
+    /// FP/SIMD registers, only valid if the running task owns the FPU
+    fp_simd: FpSimdState,
+}
+
+// Keep in sync with the offsets in exception.s
+const _: () = assert!(core::mem::offset_of!(ExceptionContext, sp_el0) == 16 * 17);
+const _: () = assert!(core::mem::offset_of!(ExceptionContext, fp_simd) == 16 * 18);
+const _: () = assert!(core::mem::size_of::<ExceptionContext>() == 16 * 51);
+
+/// Dispatch the pending IRQs to the handlers registered with the IRQ manager.
+///
+/// The interrupted thread may be preempted on the way out, once the deferred tasks have run.
+fn handle_irq(e: &ExceptionContext, kind: &str) {
+    let result = irq::irq_manager().and_then(|manager| manager.handle_pending_irqs());
+    if let Err(err) = result {
+        println!("Failed to handle IRQ: {}", err);
+        default_exception_handler(e, kind);
+    }
+
+    task_queue::run_pending_tasks();
+    thread::preempt_on_irq_return();
+}
+
+/// Give the FPU to the running program on its first FP/SIMD access, starting from a clean state.
+///
+/// The faulting instruction is retried once the exception returns.
+fn handle_fp_simd_trap(e: &mut ExceptionContext) {
+    e.fp_simd = FpSimdState::default();
+    fp_simd::enable();
 }
 
-fn default_exception_handler(exc: &ExceptionContext, kind: &str) {
+/// Map the page a user program faulted on if it belongs to one of its regions, or kill the program
+/// with a segmentation fault otherwise.
+fn handle_user_abort(e: &ExceptionContext) {
+    use ESR_EL1::EC::Value as EC;
+
+    let iss = e.esr_el1.abort_iss();
+    let address = FAR_EL1.get() as usize;
+    let access = if e.exception_class() == Some(EC::InstrAbortLowerEL) {
+        Access::Execute
+    } else if iss.is_set(ABORT_ISS::WnR) {
+        Access::Write
+    } else {
+        Access::Read
+    };
+
+    let status_code = iss.read(ABORT_ISS::FSC);
+    let result = match status_code {
+        // translation fault, at any level
+        0b00_0100..=0b00_0111 => {
+            println!("[Translation fault]: {:#x}", address);
+            process::handle_page_fault(address, access)
+        }
+        // permission fault, which is expected on writes to copy-on-write pages
+        0b00_1100..=0b00_1111 => process::handle_page_fault(address, access),
+        _ => Err(translate_fault_status(status_code).0),
+    };
+
+    if let Err(reason) = result {
+        let pid = process::current_pid().unwrap_or_default();
+        println!(
+            "\nProcess {} killed by segmentation fault at {:#x} ({:?}: {})\n",
+            pid, address, access, reason
+        );
+        process::exit(process::FAULT_EXIT_STATUS)
+    }
+}
+
+/// Kill the user program that caused a fault and return to the kernel flow that started it.
+///
+/// Falls back to `default_exception_handler` if no user program is running.
+fn kill_faulting_process(e: &ExceptionContext, kind: &str) -> ! {
+    let Some(pid) = process::current_pid() else {
+        default_exception_handler(e, kind);
+    };
+
+    println!(
+        "\nProcess {} killed by CPU exception (exception kind: '{}')\n\n\
+        {}\n",
+        pid, kind, e
+    );
+    process::exit(process::FAULT_EXIT_STATUS)
+}
+
+fn default_exception_handler(exc: &ExceptionContext, kind: &str) -> ! {
     panic!(
         "CPU Exception! (exception kind: '{}')\n\n\
         {}",
@@ -67,7 +193,7 @@ extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn current_elx_irq(e: &mut ExceptionContext) {
-    default_exception_handler(e, "current_elx_irq");
+    handle_irq(e, "current_elx_irq");
 }
 
 #[no_mangle]
@@ -79,12 +205,26 @@ extern "C" fn current_elx_serror(e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
-    default_exception_handler(e, "lower_aarch64_synchronous");
+    match e.exception_class() {
+        Some(ESR_EL1::EC::Value::SVC64) => syscall::handle_syscall(e),
+        Some(ESR_EL1::EC::Value::TrappedFP) if process::current_pid().is_some() => {
+            handle_fp_simd_trap(e)
+        }
+        Some(ESR_EL1::EC::Value::DataAbortLowerEL | ESR_EL1::EC::Value::InstrAbortLowerEL)
+            if process::current_pid().is_some() =>
+        {
+            handle_user_abort(e)
+        }
+        _ => kill_faulting_process(e, "lower_aarch64_synchronous"),
+    }
+
+    process::signal::deliver_pending(e);
 }
 
 #[no_mangle]
 extern "C" fn lower_aarch64_irq(e: &mut ExceptionContext) {
-    default_exception_handler(e, "lower_aarch64_irq");
+    handle_irq(e, "lower_aarch64_irq");
+    process::signal::deliver_pending(e);
 }
 
 #[no_mangle]
@@ -96,7 +236,7 @@ extern "C" fn lower_aarch64_serror(e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn lower_aarch32_synchronous(e: &mut ExceptionContext) {
-    default_exception_handler(e, "lower_aarch32_synchronous");
+    kill_faulting_process(e, "lower_aarch32_synchronous");
 }
 
 #[no_mangle]
@@ -139,27 +279,188 @@ impl EsrEL1 {
     fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
         self.0.read_as_enum(ESR_EL1::EC)
     }
+
+    /// The ISS, laid out as for data and instruction aborts.
+    #[inline(always)]
+    fn abort_iss(&self) -> InMemoryRegister<u64, ABORT_ISS::Register> {
+        InMemoryRegister::new(self.0.read(ESR_EL1::ISS))
+    }
 }
 
 impl core::fmt::Display for EsrEL1 {
     #[rustfmt::skip]
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use ESR_EL1::EC::Value as EC;
+
         writeln!(f, "ESR_EL1: {:#016x}", self.0.get())?;
 
         write!(f, "      Exception Class         (EC) : {:#x}", self.0.read(ESR_EL1::EC))?;
 
         let ec_translation = match self.exception_class() {
-            Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => "Data Abort, current EL",
-            _ => "N/A",
+            Some(EC::Unknown) => "Unknown reason",
+            Some(EC::TrappedWFIorWFE) => "Trapped WFI or WFE",
+            Some(EC::TrappedMCRorMRC) => "Trapped MCR or MRC (AArch32, coproc 0b1111)",
+            Some(EC::TrappedMCRRorMRRC) => "Trapped MCRR or MRRC (AArch32, coproc 0b1111)",
+            Some(EC::TrappedMCRorMRC2) => "Trapped MCR or MRC (AArch32, coproc 0b1110)",
+            Some(EC::TrappedLDCorSTC) => "Trapped LDC or STC (AArch32)",
+            Some(EC::TrappedFP) => "Trapped SVE, Advanced SIMD or floating-point access",
+            Some(EC::TrappedMRRC) => "Trapped MRRC (AArch32, coproc 0b1110)",
+            Some(EC::BranchTarget) => "Branch Target Exception",
+            Some(EC::IllegalExecutionState) => "Illegal Execution State",
+            Some(EC::SVC32) => "SVC instruction (AArch32)",
+            Some(EC::SVC64) => "SVC instruction (AArch64)",
+            Some(EC::HVC64) => "HVC instruction (AArch64)",
+            Some(EC::SMC64) => "SMC instruction (AArch64)",
+            Some(EC::TrappedMsrMrs) => "Trapped MSR, MRS or system instruction",
+            Some(EC::TrappedSve) => "Trapped SVE access",
+            Some(EC::PointerAuth) => "Pointer authentication failure",
+            Some(EC::InstrAbortLowerEL) => "Instruction Abort, lower EL",
+            Some(EC::InstrAbortCurrentEL) => "Instruction Abort, current EL",
+            Some(EC::PCAlignmentFault) => "PC alignment fault",
+            Some(EC::DataAbortLowerEL) => "Data Abort, lower EL",
+            Some(EC::DataAbortCurrentEL) => "Data Abort, current EL",
+            Some(EC::SPAlignmentFault) => "SP alignment fault",
+            Some(EC::TrappedFP32) => "Trapped floating-point exception (AArch32)",
+            Some(EC::TrappedFP64) => "Trapped floating-point exception (AArch64)",
+            Some(EC::SError) => "SError interrupt",
+            Some(EC::BreakpointLowerEL) => "Breakpoint, lower EL",
+            Some(EC::BreakpointCurrentEL) => "Breakpoint, current EL",
+            Some(EC::SoftwareStepLowerEL) => "Software Step, lower EL",
+            Some(EC::SoftwareStepCurrentEL) => "Software Step, current EL",
+            Some(EC::WatchpointLowerEL) => "Watchpoint, lower EL",
+            Some(EC::WatchpointCurrentEL) => "Watchpoint, current EL",
+            Some(EC::Bkpt32) => "BKPT instruction (AArch32)",
+            Some(EC::Brk64) => "BRK instruction (AArch64)",
+            None => "N/A",
         };
         writeln!(f, " - {}", ec_translation)?;
 
         write!(f, "      Instr Specific Syndrome (ISS): {:#x}", self.0.read(ESR_EL1::ISS))?;
 
+        match self.exception_class() {
+            Some(EC::DataAbortLowerEL | EC::DataAbortCurrentEL) => {
+                write!(f, "\n{}", DataAbortIss(self.abort_iss()))?;
+            }
+            Some(EC::InstrAbortLowerEL | EC::InstrAbortCurrentEL) => {
+                write!(f, "\n{}", InstrAbortIss(self.abort_iss()))?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Translate a DFSC or IFSC fault status code, returning the translation table level if the
+/// fault is tied to one.
+fn translate_fault_status(code: u64) -> (&'static str, Option<u64>) {
+    let level = code & 0b11;
+    match code {
+        0b00_0000..=0b00_0011 => ("Address size fault", Some(level)),
+        0b00_0100..=0b00_0111 => ("Translation fault", Some(level)),
+        0b00_1000..=0b00_1011 => ("Access flag fault", Some(level)),
+        0b00_1100..=0b00_1111 => ("Permission fault", Some(level)),
+        0b01_0000 => ("Synchronous External abort, not on table walk", None),
+        0b01_0001 => ("Synchronous Tag Check fault", None),
+        0b01_0100..=0b01_0111 => ("Synchronous External abort on table walk", Some(level)),
+        0b01_1000 => ("Synchronous parity or ECC error, not on table walk", None),
+        0b01_1100..=0b01_1111 => ("Synchronous parity or ECC error on table walk", Some(level)),
+        0b10_0001 => ("Alignment fault", None),
+        0b11_0000 => ("TLB conflict abort", None),
+        0b11_0001 => ("Unsupported atomic hardware update fault", None),
+        0b11_0100 => ("Lockdown (IMPLEMENTATION DEFINED)", None),
+        0b11_0101 => (
+            "Unsupported exclusive or atomic access (IMPLEMENTATION DEFINED)",
+            None,
+        ),
+        _ => ("N/A", None),
+    }
+}
+
+fn write_fault_status(f: &mut core::fmt::Formatter<'_>, code: u64) -> core::fmt::Result {
+    let (translation, level) = translate_fault_status(code);
+    write!(f, "{:#x} - {}", code, translation)?;
+    if let Some(level) = level {
+        write!(f, ", level {}", level)?;
+    }
+
+    Ok(())
+}
+
+struct DataAbortIss(InMemoryRegister<u64, ABORT_ISS::Register>);
+struct InstrAbortIss(InMemoryRegister<u64, ABORT_ISS::Register>);
+
+impl core::fmt::Display for DataAbortIss {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "            Data Fault Status     (DFSC): ")?;
+        write_fault_status(f, self.0.read(ABORT_ISS::FSC))?;
+        writeln!(f)?;
+
+        let access = if self.0.is_set(ABORT_ISS::WnR) { "Write" } else { "Read" };
+        write!(f, "            Write not Read         (WnR): {}", access)?;
+
+        if self.0.is_set(ABORT_ISS::S1PTW) {
+            write!(f, "\n            Stage 1 Table Walk   (S1PTW): Set")?;
+        }
+        if self.0.is_set(ABORT_ISS::FnV) {
+            write!(f, "\n            FAR not Valid          (FnV): Set")?;
+        }
+
+        // SAS and SRT only hold valid values if ISV is set
+        if !self.0.is_set(ABORT_ISS::ISV) {
+            return write!(f, "\n            Instr Syndrome Valid   (ISV): Not set");
+        }
+
+        let access_size = match self.0.read_as_enum(ABORT_ISS::SAS) {
+            Some(ABORT_ISS::SAS::Value::Byte) => "Byte",
+            Some(ABORT_ISS::SAS::Value::Halfword) => "Halfword",
+            Some(ABORT_ISS::SAS::Value::Word) => "Word",
+            Some(ABORT_ISS::SAS::Value::Doubleword) => "Doubleword",
+            None => "N/A",
+        };
+        writeln!(f)?;
+        writeln!(f, "            Syndrome Access Size   (SAS): {}", access_size)?;
+
+        let register_width = if self.0.is_set(ABORT_ISS::SF) { 'x' } else { 'w' };
+        write!(f, "            Syndrome Register      (SRT): {}{}", register_width, self.0.read(ABORT_ISS::SRT))?;
+
         Ok(())
     }
 }
 
+impl core::fmt::Display for InstrAbortIss {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "            Instr Fault Status    (IFSC): ")?;
+        write_fault_status(f, self.0.read(ABORT_ISS::FSC))?;
+
+        if self.0.is_set(ABORT_ISS::S1PTW) {
+            write!(f, "\n            Stage 1 Table Walk   (S1PTW): Set")?;
+        }
+        if self.0.is_set(ABORT_ISS::FnV) {
+            write!(f, "\n            FAR not Valid          (FnV): Set")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Clone for ExceptionContext {
+    fn clone(&self) -> Self {
+        Self {
+            gpr: self.gpr,
+            lr: self.lr,
+            elr_el1: self.elr_el1,
+            spsr_el1: SpsrEL1(InMemoryRegister::new(self.spsr_el1.0.get())),
+            esr_el1: EsrEL1(InMemoryRegister::new(self.esr_el1.0.get())),
+            sp_el0: self.sp_el0,
+            tpidr_el0: self.tpidr_el0,
+            fp_simd: self.fp_simd.clone(),
+        }
+    }
+}
+
 impl ExceptionContext {
     #[inline(always)]
     fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
@@ -184,6 +485,47 @@ impl ExceptionContext {
             ),
         }
     }
+
+    /// A copy of the registers to hand to the user program, e.g. in a signal frame.
+    ///
+    /// The FP/SIMD registers are only saved while the program owns the FPU, and are zeroed
+    /// otherwise rather than leaking whatever the context held before.
+    pub fn user_copy(&self) -> Self {
+        let mut copy = self.clone();
+        if !fp_simd::is_enabled() {
+            copy.fp_simd = FpSimdState::default();
+        }
+        copy
+    }
+
+    /// Take the user registers of `saved`, a copy the user program may have tampered with.
+    ///
+    /// Only the condition flags of its SPSR are taken, so the program cannot leave EL0 or mask
+    /// exceptions.
+    pub fn restore_user_context(&mut self, saved: &ExceptionContext) {
+        self.gpr = saved.gpr;
+        self.lr = saved.lr;
+        self.elr_el1 = saved.elr_el1;
+        self.sp_el0 = saved.sp_el0;
+        self.tpidr_el0 = saved.tpidr_el0;
+        self.fp_simd.clone_from(&saved.fp_simd);
+
+        let flags = &saved.spsr_el1.0;
+        self.spsr_el1.0.modify(
+            SPSR_EL1::N.val(flags.read(SPSR_EL1::N))
+                + SPSR_EL1::Z.val(flags.read(SPSR_EL1::Z))
+                + SPSR_EL1::C.val(flags.read(SPSR_EL1::C))
+                + SPSR_EL1::V.val(flags.read(SPSR_EL1::V)),
+        );
+    }
+}
+
+/// Append the function containing `address`, if it is known.
+fn write_symbol(f: &mut core::fmt::Formatter<'_>, address: u64) -> core::fmt::Result {
+    match symbols::resolve(address as usize) {
+        Some(symbol) => write!(f, " - {}", symbol),
+        None => Ok(()),
+    }
 }
 
 impl core::fmt::Display for ExceptionContext {
@@ -195,7 +537,14 @@ impl core::fmt::Display for ExceptionContext {
         }
 
         writeln!(f, "{}", self.spsr_el1)?;
-        writeln!(f, "ELR_EL1: {:#016x}", self.elr_el1)?;
+        write!(f, "ELR_EL1: {:#016x}", self.elr_el1)?;
+        write_symbol(f, self.elr_el1)?;
+        writeln!(f)?;
+        writeln!(f, "SP_EL0: {:#016x}", self.sp_el0)?;
+        writeln!(f, "TPIDR_EL0: {:#016x}", self.tpidr_el0)?;
+        if fp_simd::is_enabled() {
+            write!(f, "{}", self.fp_simd)?;
+        }
         writeln!(f)?;
 
         let alternating = |x| if x % 2 == 0 { "  " } else { "\n" };
@@ -205,6 +554,12 @@ impl core::fmt::Display for ExceptionContext {
         }
 
         write!(f, "      lr : {:#016x}", self.lr)?;
+        write_symbol(f, self.lr)?;
+        writeln!(f)?;
+        writeln!(f)?;
+
+        let backtrace = Backtrace::from_frame(self.elr_el1 as usize, self.gpr[29] as usize);
+        write!(f, "{}", backtrace)?;
 
         Ok(())
     }
@@ -216,6 +571,7 @@ pub unsafe fn init_exception_handling() {
     }
 
     VBAR_EL1.set(__exception_vector_start.get() as u64);
+    fp_simd::disable();
 
     barrier::isb(barrier::SY);
 }
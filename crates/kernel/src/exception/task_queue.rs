@@ -0,0 +1,108 @@
+//! Deferred interrupt work.
+//!
+//! IRQ handlers only do the minimum work to silence their device (the top half) and enqueue the
+//! rest as a task with a priority. Tasks run at the end of the IRQ handler with IRQs unmasked, so
+//! a more urgent task arriving in the meantime preempts the running one.
+//!
+//! Each core has its own queue, tasks run on the core whose IRQ handler enqueued them. Running
+//! tasks disable preemption, the interrupted thread is only switched away from once they are done.
+
+use alloc::{boxed::Box, vec::Vec};
+use device::cpu::{core_id, NUM_CORES};
+use small_std::sync::{preempt, Mutex};
+
+use super::asynchronous::{exec_with_irq_masked, exec_with_irq_unmasked};
+
+/// Priority of a deferred task, higher values are more urgent.
+pub type TaskPriority = u8;
+
+type TaskFn = Box<dyn FnOnce() + Send>;
+
+struct Task {
+    priority: TaskPriority,
+    work: TaskFn,
+}
+
+struct TaskQueue {
+    /// Pending tasks, sorted from the most to the least urgent.
+    tasks: Vec<Task>,
+    /// Priority of the innermost running task, if any.
+    running: Option<TaskPriority>,
+}
+
+static TASK_QUEUES: [Mutex<TaskQueue>; NUM_CORES] = [
+    Mutex::new(TaskQueue::new()),
+    Mutex::new(TaskQueue::new()),
+    Mutex::new(TaskQueue::new()),
+    Mutex::new(TaskQueue::new()),
+];
+
+/// Queue of the executing core, IRQs must be masked so the caller stays on it.
+fn task_queue() -> &'static Mutex<TaskQueue> {
+    &TASK_QUEUES[core_id()]
+}
+
+impl TaskQueue {
+    const fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            running: None,
+        }
+    }
+
+    fn push(&mut self, task: Task) {
+        // keep FIFO order between tasks of the same priority
+        let index = self.tasks.partition_point(|t| t.priority >= task.priority);
+        self.tasks.insert(index, task);
+    }
+
+    /// Take the most urgent task if it should preempt the running one.
+    fn pop_preempting(&mut self) -> Option<Task> {
+        let first = self.tasks.first()?;
+        if self
+            .running
+            .is_some_and(|running| first.priority <= running)
+        {
+            return None;
+        }
+
+        Some(self.tasks.remove(0))
+    }
+}
+
+/// Defer `work` until the end of the current IRQ handler on the executing core.
+pub fn enqueue<F>(priority: TaskPriority, work: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let task = Task {
+        priority,
+        work: Box::new(work),
+    };
+
+    exec_with_irq_masked(|| task_queue().lock().unwrap().push(task));
+}
+
+/// Run pending tasks that are more urgent than the one this handler interrupted, if any.
+///
+/// Called with IRQs masked at the end of IRQ handling. Tasks of lower or equal priority are left
+/// for the interrupted task's handler, which picks them up once its task finishes.
+pub fn run_pending_tasks() {
+    loop {
+        let (task, preempted) = {
+            let mut queue = task_queue().lock().unwrap();
+            let Some(task) = queue.pop_preempting() else {
+                return;
+            };
+            let preempted = queue.running.replace(task.priority);
+            (task, preempted)
+        };
+
+        {
+            let _preempt = preempt::disabled_scope();
+            exec_with_irq_unmasked(task.work);
+        }
+
+        task_queue().lock().unwrap().running = preempted;
+    }
+}
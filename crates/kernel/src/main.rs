@@ -11,21 +11,41 @@ mod cpio;
 mod devicetree;
 mod driver;
 mod exception;
+mod memory;
+mod process;
 mod shell;
+mod smp;
+mod symbols;
+mod syscall;
+mod thread;
+mod timer;
 
 use cpio::CpioArchive;
 use devicetree::DeviceTree;
 use panic_wait as _;
 use shell::commands;
-use small_std::println;
+use small_std::{println, sync::Mutex};
 
 use crate::{boot::DEVICETREE_START_ADDR, devicetree::DeviceTreeEntryValue};
 
 const INITRD_DEVICETREE_NODE: &str = "chosen";
 const INITRD_DEVICETREE_PROP: &str = "linux,initrd-start";
+const INITRD_END_DEVICETREE_PROP: &str = "linux,initrd-end";
+
+static INITRD: Mutex<Option<CpioArchive>> = Mutex::new(None);
+
+/// The initramfs loaded by the firmware, if it has been found.
+pub fn initrd() -> Option<CpioArchive> {
+    *INITRD.lock().unwrap()
+}
 
 unsafe fn kernel_init() -> ! {
     exception::init_exception_handling();
+    smp::init_this_cpu();
+    small_std::backtrace::register_stack_bounds(smp::current_stack_range);
+    small_std::backtrace::register_symbolizer(|address| {
+        symbols::resolve(address).map(|symbol| (symbol.name, symbol.offset))
+    });
 
     if let Err(e) = driver::register_drivers() {
         panic!("Failed to initialize driver subsystem: {}", e);
@@ -33,10 +53,26 @@ unsafe fn kernel_init() -> ! {
 
     device::driver::driver_manager().init_drivers();
 
+    exception::asynchronous::local_irq_unmask();
+
     // Finnaly go from unsafe to safe 🎉
     main()
 }
 
+/// Entry of the secondary cores in EL1, see `smp::start_secondary_cores`.
+unsafe fn secondary_kernel_init() -> ! {
+    exception::init_exception_handling();
+    smp::init_this_cpu();
+
+    if let Err(e) = driver::core_timer().init_secondary_core() {
+        panic!("Failed to initialize core timer: {}", e);
+    }
+
+    exception::asynchronous::local_irq_unmask();
+
+    smp::secondary_core_main()
+}
+
 fn main() -> ! {
     println!(
         "{} version {}",
@@ -53,31 +89,49 @@ fn main() -> ! {
     println!("Drivers loaded:");
     device::driver::driver_manager().enumerate();
 
+    println!("Registered IRQ handlers:");
+    if let Ok(irq_manager) = device::irq::irq_manager() {
+        irq_manager.print_handlers();
+    }
+
+    let num_cores = unsafe { smp::start_secondary_cores() };
+    println!("Cores online: {}", num_cores);
+
     println!("DTB loaded at: {:#x}", unsafe { DEVICETREE_START_ADDR });
 
     let mut cpio_start_addr = 0;
+    let mut cpio_end_addr = 0;
 
-    let devicetree = unsafe { DeviceTree::new(DEVICETREE_START_ADDR) };
+    let devicetree = unsafe { DeviceTree::new(memory::phys_to_virt(DEVICETREE_START_ADDR)) };
     if let Err(e) = devicetree.traverse(|node, props| {
         if node != INITRD_DEVICETREE_NODE {
             return;
         }
         for prop in props {
             let prop = prop.unwrap();
-            if prop.name != INITRD_DEVICETREE_PROP {
-                continue;
-            }
+            let addr = match prop.name {
+                INITRD_DEVICETREE_PROP => &mut cpio_start_addr,
+                INITRD_END_DEVICETREE_PROP => &mut cpio_end_addr,
+                _ => continue,
+            };
             match prop.value {
-                DeviceTreeEntryValue::U32(v) => cpio_start_addr = v as usize,
-                DeviceTreeEntryValue::U64(v) => cpio_start_addr = v as usize,
-                DeviceTreeEntryValue::String(v) => println!("invalid initrd start address: {}", v),
-                DeviceTreeEntryValue::Bytes(v) => println!("invalid initrd start address: {:?}", v),
+                DeviceTreeEntryValue::U32(v) => *addr = v as usize,
+                DeviceTreeEntryValue::U64(v) => *addr = v as usize,
+                DeviceTreeEntryValue::String(v) => println!("invalid initrd address: {}", v),
+                DeviceTreeEntryValue::Bytes(v) => println!("invalid initrd address: {:?}", v),
             }
         }
     }) {
         println!("Failed to parse devicetree: {}", e);
     };
 
+    if let Err(e) = memory::memory_reserve(cpio_start_addr, cpio_end_addr) {
+        println!("Failed to reserve the initrd: {}", e);
+    }
+    if let Err(e) = memory::init(&devicetree) {
+        panic!("Failed to initialize page allocator: {}", e);
+    }
+
     if cpio_start_addr == 0 {
         println!("No initrd found. Halting...");
         panic!("no initrd found");
@@ -86,7 +140,18 @@ fn main() -> ! {
 
     println!("Echoing input now");
 
-    let cpio = unsafe { CpioArchive::new(cpio_start_addr) };
+    let cpio = unsafe { CpioArchive::new(memory::phys_to_virt(cpio_start_addr)) };
+    *INITRD.lock().unwrap() = Some(cpio);
+
+    // SAFETY: Called once, on the boot stack.
+    unsafe { thread::init(boot::boot_core_stack_range()) };
+    if let Err(e) = thread::spawn("shell", move || run_shell(cpio)) {
+        panic!("Failed to start the shell: {}", e);
+    }
+    thread::idle()
+}
+
+fn run_shell(cpio: CpioArchive) -> ! {
     let mut shell = shell::Shell::new();
     let ls = commands::Ls::new(&cpio);
     let cat = commands::Cat::new(&cpio);
@@ -94,6 +159,14 @@ fn main() -> ! {
     shell.register(&commands::Hello);
     shell.register(&commands::Reboot);
     shell.register(&commands::Info);
+    shell.register(&commands::Uptime);
+    shell.register(&commands::SetTimeout);
+    shell.register(&commands::RunOn);
+    shell.register(&commands::Pages);
+    shell.register(&commands::MemInfo);
+    shell.register(&commands::Maps);
+    shell.register(&commands::Threads);
+    shell.register(&commands::Kill);
     shell.register(&ls);
     shell.register(&cat);
     shell.register(&exec);
@@ -0,0 +1,566 @@
+//! User address spaces, translated through TTBR0 with 4 level tables of the 4 KiB granule.
+//!
+//! An address space is made of virtual memory areas, whose pages are only allocated and mapped on
+//! the first access to them, see `handle_fault`. The tables and the pages mapped in them are owned
+//! by the address space and are freed with it. Pages are shared between forked address spaces, and
+//! only copied on the first write to them.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use device::cpu;
+
+use super::{
+    alloc_pages, free_pages, get_pages,
+    mmu::{
+        self, DESC_ADDR_MASK, DESC_AF, DESC_AP_EL0_RO, DESC_AP_EL0_RW, DESC_NG, DESC_NORMAL_MEMORY,
+        DESC_PAGE, DESC_PXN, DESC_SH_INNER, DESC_SW_COW, DESC_TABLE, DESC_UXN, DESC_VALID,
+    },
+    page_refs, phys_to_virt, PAGE_SIZE,
+};
+
+/// End of the lower half, the part of the virtual address space a user program can use.
+pub const USER_SPACE_END: usize = 1 << 48;
+
+const TABLE_ENTRIES: usize = 512;
+const LEVELS: usize = 4;
+
+/// Where `map_area` places areas without an address hint.
+const MMAP_BASE: usize = 0x0000_1000_0000_0000;
+
+/// Access of a user program to a page.
+///
+/// Pages that are writable or executable are readable as well, the MMU cannot map them otherwise
+/// for EL0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFlags {
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl PageFlags {
+    pub const CODE: Self = Self {
+        readable: true,
+        writable: true,
+        executable: true,
+    };
+    pub const DATA: Self = Self {
+        readable: true,
+        writable: true,
+        executable: false,
+    };
+
+    fn descriptor_bits(self) -> u64 {
+        let ap = if self.writable {
+            DESC_AP_EL0_RW
+        } else {
+            DESC_AP_EL0_RO
+        };
+        let uxn = if self.executable { 0 } else { DESC_UXN };
+        DESC_PAGE | DESC_NORMAL_MEMORY | DESC_SH_INNER | DESC_AF | DESC_NG | DESC_PXN | ap | uxn
+    }
+
+    fn from_descriptor(descriptor: u64) -> Self {
+        Self {
+            readable: true,
+            writable: descriptor & DESC_AP_EL0_RO == DESC_AP_EL0_RW,
+            executable: descriptor & DESC_UXN == 0,
+        }
+    }
+
+    pub fn allows(self, access: Access) -> bool {
+        match access {
+            Access::Read => self.readable,
+            Access::Write => self.writable,
+            Access::Execute => self.executable,
+        }
+    }
+}
+
+impl core::fmt::Display for PageFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let flag = |set, c| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.readable, 'r'),
+            flag(self.writable, 'w'),
+            flag(self.executable, 'x')
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// What the pages of an area are filled with when they are first accessed.
+#[derive(Debug, Clone, Copy)]
+pub enum Backing {
+    /// Zeroes.
+    Anonymous,
+    /// The bytes of a file from `offset` on, and zeroes past its end. Writes are not written back.
+    File {
+        name: &'static str,
+        content: &'static [u8],
+        offset: usize,
+    },
+}
+
+/// Virtual memory area, a page aligned range of the address space the user program may access.
+#[derive(Debug, Clone)]
+pub struct Vma {
+    pub range: Range<usize>,
+    pub prot: PageFlags,
+    pub backing: Backing,
+}
+
+impl Vma {
+    /// The part of the area in `range`, which must be inside of it.
+    fn slice(&self, range: Range<usize>) -> Self {
+        let backing = match self.backing {
+            Backing::Anonymous => Backing::Anonymous,
+            Backing::File {
+                name,
+                content,
+                offset,
+            } => Backing::File {
+                name,
+                content,
+                offset: offset + (range.start - self.range.start),
+            },
+        };
+        Self {
+            range,
+            prot: self.prot,
+            backing,
+        }
+    }
+}
+
+pub struct AddressSpace {
+    /// Physical address of the level 0 table.
+    pgd: usize,
+    /// Sorted by address.
+    vmas: Vec<Vma>,
+}
+
+impl AddressSpace {
+    pub fn new() -> Result<Self, &'static str> {
+        Ok(Self {
+            pgd: alloc_zeroed_page()?,
+            vmas: Vec::new(),
+        })
+    }
+
+    /// Add an area, which must not overlap the others. Its pages are mapped on demand.
+    pub fn add_vma(&mut self, vma: Vma) -> Result<(), &'static str> {
+        let range = &vma.range;
+        if range.start % PAGE_SIZE != 0 || range.end % PAGE_SIZE != 0 || range.is_empty() {
+            return Err("area is not page aligned");
+        }
+        if range.end > USER_SPACE_END {
+            return Err("area is not in the user space");
+        }
+        if self
+            .vmas
+            .iter()
+            .any(|other| other.range.start < range.end && range.start < other.range.end)
+        {
+            return Err("area overlaps another one");
+        }
+
+        let index = self
+            .vmas
+            .partition_point(|other| other.range.start < range.start);
+        self.vmas.insert(index, vma);
+        Ok(())
+    }
+
+    /// Add an area of `len` bytes, rounded up to whole pages, and return its start.
+    ///
+    /// The area is placed at `hint` if it is free, or after it otherwise. With `fixed`, it is
+    /// placed at `hint` in any case, replacing what was mapped there.
+    pub fn map_area(
+        &mut self,
+        hint: usize,
+        len: usize,
+        prot: PageFlags,
+        backing: Backing,
+        fixed: bool,
+    ) -> Result<usize, &'static str> {
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .filter(|&len| len > 0)
+            .ok_or("invalid length")?;
+
+        let start = if fixed {
+            let end = hint.checked_add(len).ok_or("invalid length")?;
+            self.unmap(hint..end)?;
+            hint
+        } else {
+            let hint = match hint / PAGE_SIZE * PAGE_SIZE {
+                0 => MMAP_BASE,
+                hint => hint,
+            };
+            self.find_free_range(hint, len)
+                .ok_or("no free range in the address space")?
+        };
+
+        self.add_vma(Vma {
+            range: start..start + len,
+            prot,
+            backing,
+        })?;
+        Ok(start)
+    }
+
+    /// Remove the areas in `range`, shrinking or splitting those partially in it, and free their
+    /// pages.
+    pub fn unmap(&mut self, range: Range<usize>) -> Result<(), &'static str> {
+        if range.start % PAGE_SIZE != 0 || range.end > USER_SPACE_END {
+            return Err("invalid range");
+        }
+        let range = range.start..range.end.next_multiple_of(PAGE_SIZE);
+
+        let mut vmas = Vec::with_capacity(self.vmas.len() + 1);
+        for vma in self.vmas.drain(..) {
+            if vma.range.end <= range.start || range.end <= vma.range.start {
+                vmas.push(vma);
+                continue;
+            }
+            if vma.range.start < range.start {
+                vmas.push(vma.slice(vma.range.start..range.start));
+            }
+            if range.end < vma.range.end {
+                vmas.push(vma.slice(range.end..vma.range.end));
+            }
+        }
+        self.vmas = vmas;
+
+        self.for_each_page(|virt_addr, entry| {
+            if range.contains(&virt_addr) {
+                free_pages((*entry & DESC_ADDR_MASK) as usize).unwrap();
+                *entry = 0;
+            }
+        });
+        mmu::flush_user_tlb();
+
+        Ok(())
+    }
+
+    /// The areas of the address space, sorted by address.
+    pub fn vmas(&self) -> &[Vma] {
+        &self.vmas
+    }
+
+    /// The area `virt_addr` belongs to.
+    pub fn vma(&self, virt_addr: usize) -> Option<&Vma> {
+        self.vmas.iter().find(|vma| vma.range.contains(&virt_addr))
+    }
+
+    /// Resolve a fault on `virt_addr` by mapping the page of its area, filled from its backing.
+    ///
+    /// A write to a page shared copy-on-write gives it its own copy. Fails if the address is
+    /// outside of any area, if the area does not allow `access`, or if the page is already mapped
+    /// otherwise.
+    pub fn handle_fault(&mut self, virt_addr: usize, access: Access) -> Result<(), &'static str> {
+        let vma = self.vma(virt_addr).ok_or("address outside of any area")?;
+        if !vma.prot.allows(access) {
+            return Err("access not allowed in the area");
+        }
+        let (prot, backing, vma_start) = (vma.prot, vma.backing, vma.range.start);
+
+        let page_addr = virt_addr / PAGE_SIZE * PAGE_SIZE;
+        if let Some(entry) = self.walk(page_addr, false)? {
+            // SAFETY: `walk` points into a table of this address space, borrowed mutably.
+            let entry = unsafe { &mut *entry };
+            if *entry & DESC_VALID != 0 {
+                if access != Access::Write || *entry & DESC_SW_COW == 0 {
+                    return Err("access not allowed to the page");
+                }
+                copy_on_write(entry)?;
+                mmu::flush_user_tlb_page(page_addr);
+                return Ok(());
+            }
+        }
+
+        let page = alloc_zeroed_page()?;
+        if let Backing::File {
+            content, offset, ..
+        } = backing
+        {
+            let offset = offset + (page_addr - vma_start);
+            if offset < content.len() {
+                let chunk = &content[offset..content.len().min(offset + PAGE_SIZE)];
+                // SAFETY: The page has just been allocated.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        chunk.as_ptr(),
+                        phys_to_virt(page) as *mut u8,
+                        chunk.len(),
+                    )
+                };
+            }
+        }
+
+        if prot.executable {
+            // the instructions were written through the data cache
+            cpu::sync_icache_range(phys_to_virt(page), PAGE_SIZE);
+        }
+
+        if let Err(e) = self.map_page(page_addr, page, prot) {
+            free_pages(page).unwrap();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Map every page of `start..start + len` that is not yet, so that the kernel can `access` it
+    /// on behalf of the user program without faulting.
+    pub fn fault_in_range(
+        &mut self,
+        start: usize,
+        len: usize,
+        access: Access,
+    ) -> Result<(), &'static str> {
+        let end = start.checked_add(len).ok_or("range overflows")?;
+        if end > USER_SPACE_END {
+            return Err("range is not in the user space");
+        }
+
+        let first_page = start / PAGE_SIZE * PAGE_SIZE;
+        for page in (first_page..end).step_by(PAGE_SIZE) {
+            match self.translate(page) {
+                Some((_, flags)) if flags.allows(access) => {}
+                _ => self.handle_fault(page, access)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Duplicate the address space, sharing its pages.
+    ///
+    /// Writable pages become read-only in both address spaces, until `handle_fault` copies them
+    /// on the first write.
+    pub fn fork(&mut self) -> Result<Self, &'static str> {
+        let mut child = Self::new()?;
+        child.vmas.clone_from(&self.vmas);
+
+        let mut result = Ok(());
+        self.for_each_page(|virt_addr, entry| {
+            if result.is_err() {
+                return;
+            }
+            if *entry & DESC_AP_EL0_RO == DESC_AP_EL0_RW {
+                *entry |= DESC_AP_EL0_RO | DESC_SW_COW;
+            }
+
+            let page = (*entry & DESC_ADDR_MASK) as usize;
+            get_pages(page).unwrap();
+            result = child.set_entry(virt_addr, *entry);
+            if result.is_err() {
+                free_pages(page).unwrap();
+            }
+        });
+        // the entries of this address space, which may be active, have changed
+        mmu::flush_user_tlb();
+
+        result.map(|()| child)
+    }
+
+    /// Start of the first free range of `len` bytes from `hint` on.
+    fn find_free_range(&self, hint: usize, len: usize) -> Option<usize> {
+        let mut start = hint;
+        for vma in &self.vmas {
+            if vma.range.end <= start {
+                continue;
+            }
+            if start.checked_add(len)? <= vma.range.start {
+                break;
+            }
+            start = vma.range.end;
+        }
+        (start.checked_add(len)? <= USER_SPACE_END).then_some(start)
+    }
+
+    /// Map the page at physical address `phys_addr` at `virt_addr`, which takes over the page.
+    fn map_page(
+        &mut self,
+        virt_addr: usize,
+        phys_addr: usize,
+        flags: PageFlags,
+    ) -> Result<(), &'static str> {
+        self.set_entry(
+            virt_addr,
+            phys_addr as u64 & DESC_ADDR_MASK | flags.descriptor_bits(),
+        )
+    }
+
+    fn set_entry(&mut self, virt_addr: usize, descriptor: u64) -> Result<(), &'static str> {
+        let entry = self.walk(virt_addr, true)?.ok_or("address not mapped")?;
+        // SAFETY: `walk` points into a table of this address space, borrowed mutably.
+        unsafe {
+            if *entry & DESC_VALID != 0 {
+                return Err("address already mapped");
+            }
+            *entry = descriptor;
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the virtual address and the level 3 entry of every mapped page.
+    fn for_each_page(&mut self, mut f: impl FnMut(usize, &mut u64)) {
+        visit_pages(self.pgd, 0, 0, &mut f);
+    }
+
+    /// Physical address and access flags of the page mapped at `virt_addr`.
+    pub fn translate(&self, virt_addr: usize) -> Option<(usize, PageFlags)> {
+        // SAFETY: `walk` points into a table of this address space.
+        let entry = unsafe { *self.walk(virt_addr, false).ok()?? };
+        if entry & DESC_VALID == 0 {
+            return None;
+        }
+        let phys_addr = (entry & DESC_ADDR_MASK) as usize + virt_addr % PAGE_SIZE;
+        Some((phys_addr, PageFlags::from_descriptor(entry)))
+    }
+
+    /// Translate the lower half through this address space.
+    ///
+    /// # Safety
+    ///
+    /// - The address space must stay alive until TTBR0 points elsewhere
+    pub unsafe fn activate(&self) {
+        mmu::set_user_table(self.pgd);
+    }
+
+    /// The level 3 entry of `virt_addr`, creating the missing tables on the way if `create` is
+    /// set, otherwise `None` if they are missing.
+    ///
+    /// The entry is only valid as long as the address space, and only writable through `&mut self`.
+    fn walk(&self, virt_addr: usize, create: bool) -> Result<Option<*mut u64>, &'static str> {
+        if virt_addr >= USER_SPACE_END {
+            return Err("address not in the user space");
+        }
+
+        let mut table = self.pgd;
+        for level in 0..LEVELS {
+            let entry = table_entry(table, table_index(virt_addr, level));
+            if level == LEVELS - 1 {
+                return Ok(Some(entry));
+            }
+
+            // SAFETY: The entry belongs to a table of this address space, and no reference to it
+            // is held.
+            unsafe {
+                if *entry & DESC_VALID == 0 {
+                    if !create {
+                        return Ok(None);
+                    }
+                    *entry = alloc_zeroed_page()? as u64 | DESC_TABLE;
+                }
+                table = (*entry & DESC_ADDR_MASK) as usize;
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        free_table(self.pgd, 0);
+    }
+}
+
+fn visit_pages(table: usize, level: usize, base: usize, f: &mut impl FnMut(usize, &mut u64)) {
+    for index in 0..TABLE_ENTRIES {
+        // SAFETY: The table belongs to the address space borrowed mutably by `for_each_page`.
+        let entry = unsafe { &mut *table_entry(table, index) };
+        if *entry & DESC_VALID == 0 {
+            continue;
+        }
+
+        let virt_addr = base | index << table_shift(level);
+        if level == LEVELS - 1 {
+            f(virt_addr, entry);
+        } else {
+            visit_pages((*entry & DESC_ADDR_MASK) as usize, level + 1, virt_addr, f);
+        }
+    }
+}
+
+/// Make the copy-on-write page of `entry` writable, copying it unless no one else shares it.
+fn copy_on_write(entry: &mut u64) -> Result<(), &'static str> {
+    let shared = (*entry & DESC_ADDR_MASK) as usize;
+    let writable = *entry & !(DESC_AP_EL0_RO | DESC_SW_COW) | DESC_AP_EL0_RW;
+
+    if page_refs(shared)? == 1 {
+        *entry = writable;
+        return Ok(());
+    }
+
+    let page = alloc_pages(0).ok_or("out of memory")?;
+    // SAFETY: The page has just been allocated, the shared one is mapped in this address space.
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            phys_to_virt(shared) as *const u8,
+            phys_to_virt(page) as *mut u8,
+            PAGE_SIZE,
+        )
+    };
+    if *entry & DESC_UXN == 0 {
+        // the copy holds instructions, e.g. of a writable code area
+        cpu::sync_icache_range(phys_to_virt(page), PAGE_SIZE);
+    }
+    *entry = writable & !DESC_ADDR_MASK | page as u64;
+    free_pages(shared).unwrap();
+
+    Ok(())
+}
+
+/// Free the table at physical address `table` of `level`, with all tables and pages below.
+fn free_table(table: usize, level: usize) {
+    for index in 0..TABLE_ENTRIES {
+        // SAFETY: The table is being freed along with the address space, borrowed mutably.
+        let entry = unsafe { *table_entry(table, index) };
+        if entry & DESC_VALID == 0 {
+            continue;
+        }
+
+        let next = (entry & DESC_ADDR_MASK) as usize;
+        if level == LEVELS - 1 {
+            free_pages(next).unwrap();
+        } else {
+            free_table(next, level + 1);
+        }
+    }
+    free_pages(table).unwrap();
+}
+
+/// Shift of the virtual address bits indexing the table of `level`.
+fn table_shift(level: usize) -> usize {
+    12 + 9 * (LEVELS - 1 - level)
+}
+
+fn table_index(virt_addr: usize, level: usize) -> usize {
+    (virt_addr >> table_shift(level)) % TABLE_ENTRIES
+}
+
+/// Entry `index` of the table at physical address `table`, through the kernel mapping.
+///
+/// A raw pointer, as every walk through the table reaches the same entry.
+fn table_entry(table: usize, index: usize) -> *mut u64 {
+    (phys_to_virt(table) as *mut u64).wrapping_add(index)
+}
+
+fn alloc_zeroed_page() -> Result<usize, &'static str> {
+    let page = alloc_pages(0).ok_or("out of memory")?;
+    // SAFETY: The page has just been allocated.
+    unsafe { core::ptr::write_bytes(phys_to_virt(page) as *mut u8, 0, PAGE_SIZE) };
+    Ok(page)
+}
@@ -0,0 +1,281 @@
+//! Buddy system page frame allocator.
+//!
+//! Memory is handed out in blocks of `2^order` contiguous pages, aligned to their size relative
+//! to the start of the managed range. Freed blocks are merged with their buddy whenever it is free
+//! as well. The bookkeeping lives in a frame array outside of the managed memory, so free pages
+//! are never written to.
+
+use core::{alloc::Layout, ops::Range};
+
+use small_std::println;
+
+use super::PAGE_SIZE;
+
+/// Largest block order, blocks hold up to `2^MAX_ORDER` pages.
+pub const MAX_ORDER: usize = 10;
+
+/// End marker of the free lists.
+const NIL: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameState {
+    /// First frame of a free block of the order.
+    Free(u8),
+    /// First frame of an allocated block of the order.
+    Allocated(u8),
+    /// Part of a block whose first frame is elsewhere, or not available at all.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    state: FrameState,
+    /// Neighbours in the free list, only valid for free blocks.
+    prev: u32,
+    next: u32,
+    /// Owners of the block, only valid for allocated blocks.
+    refs: u32,
+}
+
+pub struct BuddyAllocator {
+    /// Physical address of the first frame.
+    base: usize,
+    frames: &'static mut [Frame],
+    /// Head of the free list of each order.
+    free_lists: [u32; MAX_ORDER + 1],
+    num_free_pages: usize,
+    /// Whether to log split and merge events.
+    debug: bool,
+}
+
+impl BuddyAllocator {
+    /// Layout of the frame array needed to manage the pages in `range`.
+    pub fn frames_layout(range: &Range<usize>) -> Layout {
+        Layout::array::<Frame>(num_frames(range)).unwrap()
+    }
+
+    /// Manage the pages in `range`, initially all unavailable.
+    ///
+    /// # Safety
+    ///
+    /// - `frames` must point to memory of `frames_layout(&range)`, owned by the allocator forever
+    pub unsafe fn new(range: Range<usize>, frames: *mut u8) -> Self {
+        let frames = core::slice::from_raw_parts_mut(frames as *mut Frame, num_frames(&range));
+        frames.fill(Frame {
+            state: FrameState::Unavailable,
+            prev: NIL,
+            next: NIL,
+            refs: 0,
+        });
+        Self {
+            base: range.start / PAGE_SIZE * PAGE_SIZE,
+            frames,
+            free_lists: [NIL; MAX_ORDER + 1],
+            num_free_pages: 0,
+            debug: false,
+        }
+    }
+
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Number of pages that are free.
+    pub fn num_free_pages(&self) -> usize {
+        self.num_free_pages
+    }
+
+    /// Number of free blocks of each order.
+    pub fn free_blocks(&self) -> [usize; MAX_ORDER + 1] {
+        core::array::from_fn(|order| {
+            let mut count = 0;
+            let mut index = self.free_lists[order];
+            while index != NIL {
+                count += 1;
+                index = self.frames[index as usize].next;
+            }
+            count
+        })
+    }
+
+    /// Make the whole pages in `range` available for allocation.
+    pub fn add_free_range(&mut self, range: Range<usize>) {
+        let start = range.start.max(self.base).div_ceil(PAGE_SIZE) - self.base / PAGE_SIZE;
+        let end = (range.end / PAGE_SIZE)
+            .saturating_sub(self.base / PAGE_SIZE)
+            .min(self.frames.len());
+
+        // hand the pages to `free_block` as the largest aligned blocks that fit, so they merge
+        // into the same blocks as if they had been split from them
+        let mut index = start;
+        while index < end {
+            let order = (0..=MAX_ORDER)
+                .rev()
+                .find(|&order| index % (1 << order) == 0 && index + (1 << order) <= end)
+                .unwrap();
+            self.frames[index].state = FrameState::Allocated(order as u8);
+            self.free_block(index, order);
+            index += 1 << order;
+        }
+    }
+
+    /// Allocate `2^order` contiguous pages, returning the address of the first one.
+    pub fn alloc_pages(&mut self, order: usize) -> Option<usize> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        let mut current_order = (order..=MAX_ORDER).find(|&o| self.free_lists[o] != NIL)?;
+        let index = self.free_lists[current_order] as usize;
+        self.remove_free(index, current_order);
+
+        // give back the upper halves until the block has the requested size
+        while current_order > order {
+            current_order -= 1;
+            let buddy = index + (1 << current_order);
+            self.push_free(buddy, current_order);
+            if self.debug {
+                println!(
+                    "[buddy] split order {} block {:#x} into {:#x} and {:#x}",
+                    current_order + 1,
+                    self.address_of(index),
+                    self.address_of(index),
+                    self.address_of(buddy)
+                );
+            }
+        }
+
+        self.frames[index].state = FrameState::Allocated(order as u8);
+        self.frames[index].refs = 1;
+        self.num_free_pages -= 1 << order;
+        if self.debug {
+            println!(
+                "[buddy] allocate order {} block {:#x}",
+                order,
+                self.address_of(index)
+            );
+        }
+
+        Some(self.address_of(index))
+    }
+
+    /// Take another reference to the block starting at `address`, which is then only freed once
+    /// `free_pages` has been called for every reference.
+    pub fn get_pages(&mut self, address: usize) -> Result<(), &'static str> {
+        let index = self.allocated_index(address)?;
+        self.frames[index].refs += 1;
+        Ok(())
+    }
+
+    /// Number of references to the block starting at `address`.
+    pub fn page_refs(&self, address: usize) -> Result<usize, &'static str> {
+        let index = self.allocated_index(address)?;
+        Ok(self.frames[index].refs as usize)
+    }
+
+    /// Drop a reference to the block starting at `address`, as returned by `alloc_pages`, and
+    /// free it if it was the last one.
+    pub fn free_pages(&mut self, address: usize) -> Result<(), &'static str> {
+        let index = self.allocated_index(address)?;
+        let FrameState::Allocated(order) = self.frames[index].state else {
+            unreachable!()
+        };
+
+        self.frames[index].refs -= 1;
+        if self.frames[index].refs > 0 {
+            return Ok(());
+        }
+
+        if self.debug {
+            println!("[buddy] free order {} block {:#x}", order, address);
+        }
+        self.free_block(index, order as usize);
+
+        Ok(())
+    }
+
+    /// Put the allocated block at `index` back, merging it with its free buddies.
+    fn free_block(&mut self, mut index: usize, mut order: usize) {
+        self.frames[index].state = FrameState::Unavailable;
+        self.num_free_pages += 1 << order;
+
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if self.frames.get(buddy).map(|frame| frame.state)
+                != Some(FrameState::Free(order as u8))
+            {
+                break;
+            }
+
+            self.remove_free(buddy, order);
+            self.frames[buddy].state = FrameState::Unavailable;
+            if self.debug {
+                println!(
+                    "[buddy] merge order {} blocks {:#x} and {:#x}",
+                    order,
+                    self.address_of(index.min(buddy)),
+                    self.address_of(index.max(buddy))
+                );
+            }
+
+            index = index.min(buddy);
+            order += 1;
+        }
+
+        self.push_free(index, order);
+    }
+
+    fn push_free(&mut self, index: usize, order: usize) {
+        let head = self.free_lists[order];
+        self.frames[index] = Frame {
+            state: FrameState::Free(order as u8),
+            prev: NIL,
+            next: head,
+            refs: 0,
+        };
+        if head != NIL {
+            self.frames[head as usize].prev = index as u32;
+        }
+        self.free_lists[order] = index as u32;
+    }
+
+    fn remove_free(&mut self, index: usize, order: usize) {
+        let Frame { prev, next, .. } = self.frames[index];
+        match prev {
+            NIL => self.free_lists[order] = next,
+            prev => self.frames[prev as usize].next = next,
+        }
+        if next != NIL {
+            self.frames[next as usize].prev = prev;
+        }
+        self.frames[index].state = FrameState::Unavailable;
+    }
+
+    fn allocated_index(&self, address: usize) -> Result<usize, &'static str> {
+        let index = self.index_of(address).ok_or("address out of range")?;
+        match self.frames[index].state {
+            FrameState::Allocated(_) => Ok(index),
+            _ => Err("address is not an allocated block"),
+        }
+    }
+
+    fn address_of(&self, index: usize) -> usize {
+        self.base + index * PAGE_SIZE
+    }
+
+    fn index_of(&self, address: usize) -> Option<usize> {
+        if address % PAGE_SIZE != 0 {
+            return None;
+        }
+        let index = address.checked_sub(self.base)? / PAGE_SIZE;
+        (index < self.frames.len()).then_some(index)
+    }
+}
+
+/// Number of frames covering the whole pages of `range`.
+fn num_frames(range: &Range<usize>) -> usize {
+    range
+        .end
+        .saturating_sub(range.start / PAGE_SIZE * PAGE_SIZE)
+        / PAGE_SIZE
+}
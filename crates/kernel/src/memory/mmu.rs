@@ -0,0 +1,185 @@
+//! Translation tables and MMU configuration of the kernel.
+//!
+//! The kernel runs in the higher half: physical address `x` is mapped at `KERNEL_VIRT_OFFSET + x`
+//! through TTBR1, with 2 MiB blocks of normal memory for RAM, device memory for the peripherals,
+//! and a single 1 GiB block for the local peripherals. TTBR0 holds the address space of the running
+//! user program, see `AddressSpace`, or an identity map of RAM for the kernel otherwise.
+//!
+//! The tables are filled and the MMU is configured by `boot.s` while still in EL2, so that the
+//! kernel is entered in EL1 at its virtual address.
+//!
+//! The data cache is on as well, so memory written around it, by the code running with the MMU off
+//! or by the VideoCore, is cleaned and invalidated on the way: see `boot.s`, `smp` and the mailbox.
+
+use core::arch::asm;
+
+/// Start of the higher half, where the physical memory is mapped for the kernel.
+pub const KERNEL_VIRT_OFFSET: usize = 0xFFFF_0000_0000_0000;
+
+/// Size of the address spaces of both TTBR0 and TTBR1, 48 bits.
+const VIRT_ADDR_BITS: u64 = 48;
+
+/// Entries of a translation table of the 4 KiB granule.
+const TABLE_ENTRIES: usize = 512;
+
+// Descriptor bits.
+pub const DESC_VALID: u64 = 1 << 0;
+pub const DESC_TABLE: u64 = 0b11;
+pub const DESC_PAGE: u64 = 0b11;
+const DESC_BLOCK: u64 = 0b01;
+const DESC_ATTR_INDEX_SHIFT: u64 = 2;
+pub const DESC_AP_EL0_RW: u64 = 0b01 << 6;
+pub const DESC_AP_EL0_RO: u64 = 0b11 << 6;
+pub const DESC_SH_INNER: u64 = 0b11 << 8;
+pub const DESC_AF: u64 = 1 << 10;
+pub const DESC_NG: u64 = 1 << 11;
+pub const DESC_PXN: u64 = 1 << 53;
+pub const DESC_UXN: u64 = 1 << 54;
+/// Software defined: read-only page of a writable region, shared until the first write to it.
+pub const DESC_SW_COW: u64 = 1 << 55;
+
+/// Bits of a descriptor holding the physical address of the next table or of the page.
+pub const DESC_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+/// MAIR_EL1 index of Device-nGnRnE memory.
+const MAIR_DEVICE_INDEX: u64 = 0;
+/// MAIR_EL1 index of normal write-back, read/write-allocate memory.
+pub const MAIR_NORMAL_INDEX: u64 = 1;
+
+pub const DESC_NORMAL_MEMORY: u64 = MAIR_NORMAL_INDEX << DESC_ATTR_INDEX_SHIFT;
+
+pub const MAIR_EL1_VALUE: u64 = 0x00 << (8 * MAIR_DEVICE_INDEX) | 0xff << (8 * MAIR_NORMAL_INDEX);
+
+/// 4 KiB granule, 48-bit address spaces, write-back inner shareable table walks.
+pub const TCR_EL1_VALUE: u64 = {
+    let t0sz = 64 - VIRT_ADDR_BITS;
+    let t1sz = (64 - VIRT_ADDR_BITS) << 16;
+    let walk0 = 0b01 << 8 | 0b01 << 10 | 0b11 << 12;
+    let walk1 = 0b01 << 24 | 0b01 << 26 | 0b11 << 28;
+    let tg0_4k = 0b00 << 14;
+    let tg1_4k = 0b10 << 30;
+    // 32-bit physical addresses
+    let ips = 0b000 << 32;
+    t0sz | t1sz | walk0 | walk1 | tg0_4k | tg1_4k | ips
+};
+
+/// MMU, data cache and instruction cache on.
+pub const SCTLR_EL1_VALUE: u64 = {
+    let res1 = 1 << 29 | 1 << 28 | 1 << 23 | 1 << 22 | 1 << 20 | 1 << 11;
+    let m = 1 << 0;
+    let c = 1 << 2;
+    let i = 1 << 12;
+    res1 | m | c | i
+};
+
+/// Kernel RAM, not executable from EL0.
+pub const KERNEL_NORMAL_BLOCK: u64 =
+    DESC_BLOCK | DESC_NORMAL_MEMORY | DESC_SH_INNER | DESC_AF | DESC_UXN;
+
+/// Kernel MMIO, never executable.
+pub const KERNEL_DEVICE_BLOCK: u64 =
+    DESC_BLOCK | MAIR_DEVICE_INDEX << DESC_ATTR_INDEX_SHIFT | DESC_AF | DESC_PXN | DESC_UXN;
+
+/// Identity mapped RAM, only accessible from EL1 and never executable.
+pub const IDENTITY_NORMAL_BLOCK: u64 = KERNEL_NORMAL_BLOCK | DESC_PXN;
+
+#[repr(C, align(4096))]
+pub struct PageTable([u64; TABLE_ENTRIES]);
+
+impl PageTable {
+    const fn new() -> Self {
+        Self([0; TABLE_ENTRIES])
+    }
+}
+
+// Filled by `__create_page_tables` in boot.s.
+#[no_mangle]
+static mut KERNEL_PGD: PageTable = PageTable::new();
+#[no_mangle]
+static mut KERNEL_PUD: PageTable = PageTable::new();
+#[no_mangle]
+static mut KERNEL_PMD: PageTable = PageTable::new();
+#[no_mangle]
+static mut IDENTITY_PGD: PageTable = PageTable::new();
+#[no_mangle]
+static mut IDENTITY_PUD: PageTable = PageTable::new();
+#[no_mangle]
+static mut IDENTITY_PMD: PageTable = PageTable::new();
+
+/// Kernel virtual address of the physical address `phys`.
+///
+/// The offset only sets the upper bits, so this is a no-op for kernel virtual addresses.
+pub const fn phys_to_virt(phys: usize) -> usize {
+    phys | KERNEL_VIRT_OFFSET
+}
+
+/// Physical address of the kernel virtual address `virt`, a no-op for physical addresses.
+pub const fn virt_to_phys(virt: usize) -> usize {
+    virt & !KERNEL_VIRT_OFFSET
+}
+
+/// Translate the lower half through the table at physical address `pgd`.
+///
+/// # Safety
+///
+/// - `pgd` must point to a valid translation table, which must outlive its use by TTBR0
+pub unsafe fn set_user_table(pgd: usize) {
+    asm!(
+        "dsb ishst",
+        "msr TTBR0_EL1, {}",
+        "isb",
+        in(reg) pgd,
+        options(nostack),
+    );
+    // user mappings are not tagged with an ASID, so drop those of the previous table
+    flush_user_tlb();
+}
+
+/// Drop all cached translations, after entries of the active user table have changed.
+pub fn flush_user_tlb() {
+    // SAFETY: Only invalidates TLB entries.
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vmalle1",
+            "dsb ish",
+            "isb",
+            options(nostack)
+        )
+    };
+}
+
+/// Drop the cached translation of the page at `virt_addr`, after its entry has changed.
+pub fn flush_user_tlb_page(virt_addr: usize) {
+    // SAFETY: Only invalidates TLB entries.
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vaae1, {}",
+            "dsb ish",
+            "isb",
+            in(reg) virt_addr >> 12,
+            options(nostack)
+        )
+    };
+}
+
+/// Physical address of the table translating the lower half.
+pub fn user_table() -> usize {
+    let pgd: usize;
+    // SAFETY: Only reads TTBR0_EL1.
+    unsafe { asm!("mrs {}, TTBR0_EL1", out(reg) pgd, options(nomem, nostack)) };
+    pgd
+}
+
+/// Physical address of the identity map of RAM.
+pub fn identity_table() -> usize {
+    // SAFETY: Only takes the address of the table.
+    virt_to_phys(unsafe { core::ptr::addr_of!(IDENTITY_PGD) } as usize)
+}
+
+/// Translate the lower half through the identity map again, e.g. before freeing a user table.
+pub fn reset_user_table() {
+    // SAFETY: The identity map is static.
+    unsafe { set_user_table(identity_table()) };
+}
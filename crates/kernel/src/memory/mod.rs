@@ -0,0 +1,268 @@
+//! Physical memory management.
+//!
+//! All usable RAM is managed by a buddy allocator in units of pages, except for the ranges
+//! reserved during boot. The size of RAM comes from the devicetree `/memory` node, or from the
+//! mailbox if the devicetree does not describe it.
+
+mod address_space;
+mod buddy;
+pub mod mmu;
+mod slab;
+mod startup;
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use small_std::{println, sync::IRQSafeMutex};
+
+pub use self::{
+    address_space::{Access, AddressSpace, Backing, PageFlags, Vma},
+    buddy::MAX_ORDER,
+    mmu::{phys_to_virt, virt_to_phys},
+    slab::{size_class, SlabAllocator, MIN_OBJECT_SIZE, NUM_SIZE_CLASSES},
+    startup::memory_reserve,
+};
+use self::{buddy::BuddyAllocator, startup::ReservedRanges};
+use crate::{
+    allocator, boot,
+    devicetree::{DeviceTree, DeviceTreeEntryValue},
+    driver,
+};
+
+pub const PAGE_SIZE: usize = 4096;
+
+const MEMORY_DEVICETREE_NODE: &str = "memory";
+const ADDRESS_CELLS_DEVICETREE_PROP: &str = "#address-cells";
+const SIZE_CELLS_DEVICETREE_PROP: &str = "#size-cells";
+const REG_DEVICETREE_PROP: &str = "reg";
+
+/// The page holding the spin table the secondary cores wait on.
+const SPIN_TABLE_RANGE: Range<usize> = 0..PAGE_SIZE;
+
+static PAGE_ALLOCATOR: IRQSafeMutex<Option<BuddyAllocator>> = IRQSafeMutex::new(None);
+
+/// Set up the page allocator over the RAM described by `devicetree`.
+///
+/// Besides the ranges passed to `memory_reserve` before, the memory used by the kernel and the
+/// devicetree is never handed out.
+pub fn init(devicetree: &DeviceTree) -> Result<(), &'static str> {
+    reserve_boot_memory(devicetree)?;
+
+    let ram = match find_ram(devicetree) {
+        Some(ram) => ram,
+        None => {
+            let info = driver::mailbox().get_arm_memory()?;
+            let start = info.base_address as usize;
+            start..start + info.size as usize
+        }
+    };
+
+    let frames_layout = BuddyAllocator::frames_layout(&ram);
+    let frames = startup::startup_alloc(ram.clone(), frames_layout.size(), frames_layout.align())
+        .ok_or("not enough memory for the page frame array")?;
+    // SAFETY: The frame array has been reserved by the startup allocator.
+    let mut page_allocator =
+        unsafe { BuddyAllocator::new(ram.clone(), phys_to_virt(frames) as *mut u8) };
+
+    let reserved_ranges = startup::freeze();
+    for gap in reserved_ranges.gaps(ram.clone()) {
+        page_allocator.add_free_range(gap);
+    }
+
+    print_memory_map(&ram, &reserved_ranges);
+    println!(
+        "Free pages: {} ({} KiB)",
+        page_allocator.num_free_pages(),
+        page_allocator.num_free_pages() * PAGE_SIZE / 1024
+    );
+
+    *PAGE_ALLOCATOR.lock().unwrap() = Some(page_allocator);
+
+    Ok(())
+}
+
+/// Whether the page allocator is set up.
+pub fn is_initialized() -> bool {
+    PAGE_ALLOCATOR.lock().unwrap().is_some()
+}
+
+/// Allocate `2^order` contiguous pages, returning the physical address of the first one.
+///
+/// Use `phys_to_virt` to access them.
+pub fn alloc_pages(order: usize) -> Option<usize> {
+    PAGE_ALLOCATOR.lock().unwrap().as_mut()?.alloc_pages(order)
+}
+
+/// Take another reference to the pages at `address`, see `free_pages`.
+pub fn get_pages(address: usize) -> Result<(), &'static str> {
+    PAGE_ALLOCATOR
+        .lock()
+        .unwrap()
+        .as_mut()
+        .ok_or("page allocator is not initialized")?
+        .get_pages(address)
+}
+
+/// Number of references to the pages at `address`.
+pub fn page_refs(address: usize) -> Result<usize, &'static str> {
+    PAGE_ALLOCATOR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or("page allocator is not initialized")?
+        .page_refs(address)
+}
+
+/// Free the pages at `address`, previously returned by `alloc_pages`.
+///
+/// Pages shared with `get_pages` are only freed once every reference has been dropped.
+pub fn free_pages(address: usize) -> Result<(), &'static str> {
+    PAGE_ALLOCATOR
+        .lock()
+        .unwrap()
+        .as_mut()
+        .ok_or("page allocator is not initialized")?
+        .free_pages(address)
+}
+
+/// Number of pages that are free.
+pub fn num_free_pages() -> usize {
+    PAGE_ALLOCATOR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(0, BuddyAllocator::num_free_pages)
+}
+
+/// Number of free blocks of each order.
+pub fn free_blocks() -> [usize; MAX_ORDER + 1] {
+    PAGE_ALLOCATOR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or([0; MAX_ORDER + 1], BuddyAllocator::free_blocks)
+}
+
+/// Enable or disable the log of split and merge events.
+pub fn set_debug(debug: bool) {
+    if let Some(page_allocator) = PAGE_ALLOCATOR.lock().unwrap().as_mut() {
+        page_allocator.set_debug(debug);
+    }
+}
+
+fn print_memory_map(ram: &Range<usize>, reserved_ranges: &ReservedRanges) {
+    let print_range = |range: Range<usize>, kind| {
+        println!(
+            "  {:#010x} - {:#010x} {:>8} KiB {}",
+            range.start,
+            range.end,
+            range.len() / 1024,
+            kind
+        )
+    };
+
+    println!("Memory map:");
+    let mut free_start = ram.start;
+    for range in reserved_ranges.iter() {
+        let range = range.start.max(ram.start)..range.end.min(ram.end);
+        if range.is_empty() {
+            continue;
+        }
+        if free_start < range.start {
+            print_range(free_start..range.start, "free");
+        }
+        free_start = range.end;
+        print_range(range, "reserved");
+    }
+    if free_start < ram.end {
+        print_range(free_start..ram.end, "free");
+    }
+}
+
+/// Reserve the memory the kernel and the firmware occupy.
+fn reserve_boot_memory(devicetree: &DeviceTree) -> Result<(), &'static str> {
+    memory_reserve(SPIN_TABLE_RANGE.start, SPIN_TABLE_RANGE.end)?;
+
+    // the kernel and the devicetree are accessed at their virtual addresses
+    let devicetree_blob = devicetree
+        .memory_range()
+        .map_err(|_| "invalid devicetree header")?;
+    let kernel_ranges = [
+        boot::boot_core_stack_range(),
+        boot::kernel_image_range(),
+        allocator::heap_range(),
+        devicetree_blob,
+    ];
+    for range in kernel_ranges {
+        memory_reserve(virt_to_phys(range.start), virt_to_phys(range.end))?;
+    }
+
+    let reservations = devicetree
+        .memory_reservations()
+        .map_err(|_| "invalid devicetree header")?;
+    for reservation in reservations {
+        memory_reserve(reservation.start, reservation.end)?;
+    }
+
+    Ok(())
+}
+
+/// Find the RAM in the `reg` property of the devicetree memory node.
+///
+/// Only the first region is used, the Raspberry Pi has a single one.
+fn find_ram(devicetree: &DeviceTree) -> Option<Range<usize>> {
+    // defaults from the devicetree specification
+    let mut address_cells = 2;
+    let mut size_cells = 1;
+    let mut reg = None;
+
+    let result = devicetree.traverse(|node, props| {
+        let is_root = node.is_empty();
+        let is_memory = node
+            .split('@')
+            .next()
+            .is_some_and(|name| name == MEMORY_DEVICETREE_NODE);
+        if !is_root && !is_memory {
+            return;
+        }
+
+        for prop in props.flatten() {
+            match (prop.name, prop.value) {
+                (ADDRESS_CELLS_DEVICETREE_PROP, DeviceTreeEntryValue::U32(v)) if is_root => {
+                    address_cells = v as usize
+                }
+                (SIZE_CELLS_DEVICETREE_PROP, DeviceTreeEntryValue::U32(v)) if is_root => {
+                    size_cells = v as usize
+                }
+                (REG_DEVICETREE_PROP, value) if is_memory && reg.is_none() => {
+                    reg = Some(to_be_bytes(value))
+                }
+                _ => {}
+            }
+        }
+    });
+    if let Err(e) = result {
+        println!("Failed to parse devicetree: {}", e);
+        return None;
+    }
+
+    let reg = reg?;
+    let mut cells = reg
+        .chunks_exact(4)
+        .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()) as u64);
+    let mut read_cells = |n| (0..n).try_fold(0, |acc, _| Some(acc << 32 | cells.next()?));
+
+    let start = read_cells(address_cells)? as usize;
+    let size = read_cells(size_cells)? as usize;
+    (size > 0).then_some(start..start + size)
+}
+
+/// Raw bytes of a property value, which the parser may have interpreted as a number or string.
+fn to_be_bytes(value: DeviceTreeEntryValue) -> Vec<u8> {
+    match value {
+        DeviceTreeEntryValue::U32(v) => v.to_be_bytes().to_vec(),
+        DeviceTreeEntryValue::U64(v) => v.to_be_bytes().to_vec(),
+        DeviceTreeEntryValue::String(v) => v.as_bytes().to_vec(),
+        DeviceTreeEntryValue::Bytes(v) => v.to_vec(),
+    }
+}
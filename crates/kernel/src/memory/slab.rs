@@ -0,0 +1,173 @@
+//! Object caches for small allocations, carved from pages of the page allocator.
+//!
+//! Each power of two size class from `MIN_OBJECT_SIZE` to `MAX_OBJECT_SIZE` has its own cache.
+//! A cache takes a page when it runs out of objects and splits it into objects of its size, which
+//! are linked through their first word while free. Larger allocations take whole pages.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{phys_to_virt, virt_to_phys, PAGE_SIZE};
+
+pub const MIN_OBJECT_SIZE: usize = 16;
+pub const MAX_OBJECT_SIZE: usize = 2048;
+
+pub const NUM_SIZE_CLASSES: usize =
+    (MAX_OBJECT_SIZE.trailing_zeros() - MIN_OBJECT_SIZE.trailing_zeros()) as usize + 1;
+
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+#[derive(Clone, Copy)]
+struct ObjectCache {
+    object_size: usize,
+    free_list: Option<NonNull<FreeObject>>,
+}
+
+impl ObjectCache {
+    const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            free_list: None,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<NonNull<u8>> {
+        if self.free_list.is_none() {
+            self.grow()?;
+        }
+
+        let object = self.free_list?;
+        // SAFETY: Objects in the free list are unused and hold a `FreeObject`.
+        self.free_list = unsafe { object.as_ref().next };
+        Some(object.cast())
+    }
+
+    /// # Safety
+    ///
+    /// - `ptr` must have been allocated from this cache and must not be used afterwards
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>) {
+        let object = ptr.cast::<FreeObject>();
+        object.as_ptr().write(FreeObject {
+            next: self.free_list,
+        });
+        self.free_list = Some(object);
+    }
+
+    /// Split a new page into objects.
+    fn grow(&mut self) -> Option<()> {
+        let page = phys_to_virt(super::alloc_pages(0)?);
+        for offset in (0..PAGE_SIZE).step_by(self.object_size).rev() {
+            let object = NonNull::new((page + offset) as *mut u8)?;
+            // SAFETY: The page has just been allocated and is owned by the cache.
+            unsafe { self.dealloc(object) };
+        }
+        Some(())
+    }
+}
+
+pub struct SlabAllocator {
+    caches: [ObjectCache; NUM_SIZE_CLASSES],
+}
+
+// SAFETY: The free objects are only reached through the allocator.
+unsafe impl Send for SlabAllocator {}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        let mut caches = [ObjectCache::new(0); NUM_SIZE_CLASSES];
+        let mut class = 0;
+        while class < NUM_SIZE_CLASSES {
+            caches[class].object_size = MIN_OBJECT_SIZE << class;
+            class += 1;
+        }
+        Self { caches }
+    }
+
+    /// Allocate from the size class fitting `layout`, or whole pages for large layouts.
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        match size_class(layout) {
+            Some(class) => self.caches[class].alloc(),
+            None => {
+                let address = super::alloc_pages(large_order(layout))?;
+                // blocks are only aligned to their size relative to the start of RAM
+                if address % layout.align() != 0 {
+                    super::free_pages(address).unwrap();
+                    return None;
+                }
+                NonNull::new(phys_to_virt(address) as *mut u8)
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// - `ptr` must have been returned by `alloc` with the same `layout`
+    pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        match size_class(layout) {
+            Some(class) => self.caches[class].dealloc(ptr),
+            None => super::free_pages(virt_to_phys(ptr.as_ptr() as usize)).unwrap(),
+        }
+    }
+}
+
+/// Index of the cache whose objects fit `layout`, `None` if it needs whole pages.
+pub fn size_class(layout: Layout) -> Option<usize> {
+    // objects are aligned to their size, since the pages are split in equal power of two parts
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(MIN_OBJECT_SIZE)
+        .next_power_of_two();
+    (size <= MAX_OBJECT_SIZE)
+        .then(|| (size.trailing_zeros() - MIN_OBJECT_SIZE.trailing_zeros()) as usize)
+}
+
+/// Order of the page block for an allocation too large for the object caches.
+fn large_order(layout: Layout) -> usize {
+    let pages = layout.size().max(layout.align()).div_ceil(PAGE_SIZE);
+    pages.next_power_of_two().trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn size_class_rounds_up_to_a_power_of_two() {
+        assert_eq!(size_class(layout(1, 1)), Some(0));
+        assert_eq!(size_class(layout(MIN_OBJECT_SIZE, 8)), Some(0));
+        assert_eq!(size_class(layout(MIN_OBJECT_SIZE + 1, 8)), Some(1));
+        assert_eq!(size_class(layout(100, 8)), Some(3));
+        assert_eq!(
+            size_class(layout(MAX_OBJECT_SIZE, 8)),
+            Some(NUM_SIZE_CLASSES - 1)
+        );
+        assert_eq!(size_class(layout(MAX_OBJECT_SIZE + 1, 8)), None);
+    }
+
+    #[test]
+    fn size_class_covers_the_alignment() {
+        assert_eq!(size_class(layout(8, 64)), Some(2));
+        assert_eq!(size_class(layout(8, PAGE_SIZE)), None);
+    }
+
+    #[test]
+    fn large_order_covers_the_size() {
+        assert_eq!(large_order(layout(MAX_OBJECT_SIZE + 1, 8)), 0);
+        assert_eq!(large_order(layout(PAGE_SIZE, 8)), 0);
+        assert_eq!(large_order(layout(PAGE_SIZE + 1, 8)), 1);
+        assert_eq!(large_order(layout(3 * PAGE_SIZE, 8)), 2);
+        assert_eq!(large_order(layout(4 * PAGE_SIZE, 8)), 2);
+    }
+
+    #[test]
+    fn large_order_covers_the_alignment() {
+        assert_eq!(large_order(layout(8, 4 * PAGE_SIZE)), 2);
+        assert_eq!(large_order(layout(PAGE_SIZE + 1, 8 * PAGE_SIZE)), 3);
+    }
+}
@@ -0,0 +1,110 @@
+//! Early boot memory bookkeeping, before the page allocator takes over.
+//!
+//! Physical ranges that must never be handed out are recorded with `memory_reserve`. The startup
+//! allocator serves memory from the gaps between them and reserves what it returns, so the page
+//! allocator can then be built from whatever is left.
+
+use core::ops::Range;
+
+use small_std::sync::IRQSafeMutex;
+
+use super::PAGE_SIZE;
+
+const MAX_RESERVED_RANGES: usize = 32;
+
+const EMPTY_RANGE: Range<usize> = 0..0;
+
+static RESERVED_RANGES: IRQSafeMutex<ReservedRanges> = IRQSafeMutex::new(ReservedRanges::new());
+
+/// Sorted, non-overlapping and page aligned reserved ranges.
+#[derive(Clone)]
+pub struct ReservedRanges {
+    ranges: [Range<usize>; MAX_RESERVED_RANGES],
+    len: usize,
+    /// Set once the page allocator is built, later reservations would have no effect.
+    frozen: bool,
+}
+
+impl ReservedRanges {
+    const fn new() -> Self {
+        Self {
+            ranges: [EMPTY_RANGE; MAX_RESERVED_RANGES],
+            len: 0,
+            frozen: false,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Range<usize>> {
+        self.ranges[..self.len].iter()
+    }
+
+    /// The parts of `ram` that are not reserved, in ascending order.
+    pub fn gaps(&self, ram: Range<usize>) -> impl Iterator<Item = Range<usize>> + '_ {
+        let ends = self.iter().map(|range| range.start).chain([usize::MAX]);
+        let starts = [0].into_iter().chain(self.iter().map(|range| range.end));
+        starts.zip(ends).filter_map(move |(start, end)| {
+            let gap = start.max(ram.start)..end.min(ram.end);
+            (!gap.is_empty()).then_some(gap)
+        })
+    }
+
+    fn insert(&mut self, range: Range<usize>) -> Result<(), &'static str> {
+        if self.frozen {
+            return Err("the page allocator is already set up");
+        }
+        let range = range.start / PAGE_SIZE * PAGE_SIZE..range.end.next_multiple_of(PAGE_SIZE);
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        // merge with every range it overlaps or touches, and keep the others
+        let mut ranges = [EMPTY_RANGE; MAX_RESERVED_RANGES];
+        let mut len = 0;
+        let mut merged = range;
+        for other in self.iter() {
+            if other.end < merged.start || other.start > merged.end {
+                ranges[len] = other.clone();
+                len += 1;
+            } else {
+                merged = merged.start.min(other.start)..merged.end.max(other.end);
+            }
+        }
+        if len == MAX_RESERVED_RANGES {
+            return Err("too many reserved ranges");
+        }
+        ranges[len] = merged;
+        len += 1;
+        ranges[..len].sort_unstable_by_key(|range| range.start);
+
+        self.ranges = ranges;
+        self.len = len;
+
+        Ok(())
+    }
+}
+
+/// Mark the physical memory from `start` to `end` as not usable by the page allocator.
+///
+/// The range is extended to whole pages. Must be called before `memory::init`.
+pub fn memory_reserve(start: usize, end: usize) -> Result<(), &'static str> {
+    RESERVED_RANGES.lock().unwrap().insert(start..end)
+}
+
+/// Allocate `size` bytes aligned to `align` from the unreserved parts of `ram`, and reserve them.
+pub fn startup_alloc(ram: Range<usize>, size: usize, align: usize) -> Option<usize> {
+    let mut reserved_ranges = RESERVED_RANGES.lock().unwrap();
+    let start = reserved_ranges.gaps(ram).find_map(|gap| {
+        let start = gap.start.next_multiple_of(align);
+        (start.checked_add(size)? <= gap.end).then_some(start)
+    })?;
+
+    reserved_ranges.insert(start..start + size).ok()?;
+    Some(start)
+}
+
+/// Stop accepting reservations and return the final ones.
+pub fn freeze() -> ReservedRanges {
+    let mut reserved_ranges = RESERVED_RANGES.lock().unwrap();
+    reserved_ranges.frozen = true;
+    reserved_ranges.clone()
+}
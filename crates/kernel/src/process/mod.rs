@@ -0,0 +1,379 @@
+pub mod signal;
+
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use aarch64_cpu::registers::SPSR_EL1;
+use alloc::{boxed::Box, vec::Vec};
+use small_std::sync::Mutex;
+
+use crate::{
+    cpio::CpioEntry,
+    exception::{fp_simd, ExceptionContext},
+    memory::{self, Access, AddressSpace, Backing, PageFlags, Vma, PAGE_SIZE},
+    thread::{self, WaitQueue},
+};
+
+use self::signal::{signal_exit_status, Signals, SIGSEGV};
+
+global_asm!(include_str!("process.s"));
+
+/// Virtual address user programs are loaded and entered at.
+const USER_CODE_START: usize = 0x0;
+
+/// The user stack grows down from the top of the user address space, below the last page which
+/// holds the signal return trampoline.
+const USER_STACK_END: usize = 0x0000_ffff_ffff_f000;
+const USER_STACK_SIZE: usize = 0x4000;
+
+/// Exit status of a program killed because of a fault, the status shells report for SIGSEGV.
+pub const FAULT_EXIT_STATUS: i32 = signal_exit_status(SIGSEGV);
+
+/// Callee-saved registers of the kernel flow that started a user program.
+#[repr(C)]
+#[derive(Default)]
+struct KernelContext {
+    /// x19 - x28, fp (x29) and lr (x30)
+    callee_saved: [u64; 12],
+    sp: u64,
+    daif: u64,
+}
+
+struct Process {
+    pid: u64,
+    /// PID of the parent that forked the program, 0 if it was started by the kernel or orphaned.
+    ppid: u64,
+    /// The kernel thread the program runs in.
+    tid: u64,
+    kernel_context: KernelContext,
+    address_space: AddressSpace,
+    signals: Signals,
+}
+
+// `ExceptionContext` holds the FP/SIMD registers as `u128`, but it is only copied by the assembly.
+#[allow(improper_ctypes)]
+extern "C" {
+    fn __process_enter_user(
+        kernel_context: *mut KernelContext,
+        entry: u64,
+        user_stack_end: u64,
+        spsr: u64,
+    ) -> i32;
+    fn __process_enter_user_context(
+        kernel_context: *mut KernelContext,
+        context: *const ExceptionContext,
+    ) -> i32;
+    fn __process_leave_user(kernel_context: *const KernelContext, status: i32) -> !;
+}
+
+/// A child that has exited, until its parent waits for it.
+struct Zombie {
+    pid: u64,
+    ppid: u64,
+    status: i32,
+}
+
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+
+/// The running user programs, each in its own thread. Boxed so that their kernel contexts stay in
+/// place while the list changes.
+#[allow(clippy::vec_box)]
+static PROCESSES: Mutex<Vec<Box<Process>>> = Mutex::new(Vec::new());
+
+/// Exited children not waited for yet. Only locked with `PROCESSES` held, so that a child is
+/// always found in one or the other.
+static ZOMBIES: Mutex<Vec<Zombie>> = Mutex::new(Vec::new());
+
+/// Parents waiting for a child to exit.
+static CHILD_EXIT: WaitQueue = WaitQueue::new();
+
+impl Process {
+    fn new(address_space: AddressSpace) -> Self {
+        Self {
+            pid: NEXT_PID.fetch_add(1, Ordering::Relaxed),
+            ppid: 0,
+            tid: thread::current_tid(),
+            kernel_context: KernelContext::default(),
+            address_space,
+            signals: Signals::new(),
+        }
+    }
+}
+
+/// The user program running in the executing thread.
+fn current(processes: &mut [Box<Process>]) -> Option<&mut Process> {
+    let tid = thread::current_tid();
+    processes
+        .iter_mut()
+        .find(|process| process.tid == tid)
+        .map(|process| &mut **process)
+}
+
+/// Drop the user program `pid` once it has exited with `status`, keeping the status for its
+/// parent. Its own children are orphaned.
+///
+/// Must be called from the thread of the program, after its user table has been reset.
+fn remove(pid: u64, status: i32) {
+    {
+        let mut processes = PROCESSES.lock().unwrap();
+        let index = processes
+            .iter()
+            .position(|process| process.pid == pid)
+            .unwrap();
+        let process = processes.remove(index);
+
+        for child in processes.iter_mut().filter(|child| child.ppid == pid) {
+            child.ppid = 0;
+        }
+        let mut zombies = ZOMBIES.lock().unwrap();
+        zombies.retain(|zombie| zombie.ppid != pid);
+        if process.ppid != 0 {
+            zombies.push(Zombie {
+                pid,
+                ppid: process.ppid,
+                status,
+            });
+        }
+    }
+
+    CHILD_EXIT.wake_all();
+}
+
+/// Build an address space with `program` at `USER_CODE_START` and an empty user stack.
+///
+/// Nothing is mapped yet, the pages are filled on the first access to them.
+fn load_program(program: &CpioEntry<'static>) -> Result<AddressSpace, &'static str> {
+    let mut address_space = AddressSpace::new()?;
+    address_space.add_vma(Vma {
+        range: USER_CODE_START..USER_CODE_START + program.content.len().next_multiple_of(PAGE_SIZE),
+        prot: PageFlags::CODE,
+        backing: Backing::File {
+            name: program.filename,
+            content: program.content,
+            offset: 0,
+        },
+    })?;
+    address_space.add_vma(Vma {
+        range: USER_STACK_END - USER_STACK_SIZE..USER_STACK_END,
+        prot: PageFlags::DATA,
+        backing: Backing::Anonymous,
+    })?;
+    address_space.add_vma(signal::sigreturn_trampoline_vma())?;
+    Ok(address_space)
+}
+
+/// Run `program` in EL0 until it calls `exit`, and return its exit status.
+pub fn run(program: &CpioEntry<'static>) -> Result<i32, &'static str> {
+    let address_space = load_program(program)?;
+
+    let (kernel_context, pid) = {
+        let mut processes = PROCESSES.lock().unwrap();
+        processes.push(Box::new(Process::new(address_space)));
+        let process = processes.last_mut().unwrap();
+        // SAFETY: The address space lives in `PROCESSES` until the user table is reset.
+        unsafe { process.address_space.activate() };
+        (core::ptr::addr_of_mut!(process.kernel_context), process.pid)
+    };
+
+    let spsr = SPSR_EL1::D::Masked
+        + SPSR_EL1::A::Masked
+        + SPSR_EL1::I::Unmasked
+        + SPSR_EL1::F::Masked
+        + SPSR_EL1::M::EL0t;
+
+    fp_simd::disable();
+
+    // SAFETY: The context is boxed in `PROCESSES` until the program exits.
+    let status = unsafe {
+        __process_enter_user(
+            kernel_context,
+            USER_CODE_START as u64,
+            USER_STACK_END as u64,
+            spsr.value,
+        )
+    };
+
+    memory::mmu::reset_user_table();
+    remove(pid, status);
+    Ok(status)
+}
+
+/// Duplicate the running program into a new thread, and return the PID of the child.
+///
+/// The child shares the pages of its parent copy-on-write and resumes from the same system call,
+/// which returns 0 there. The parent resumes right away, and may `wait` for the child to exit.
+pub fn fork(e: &ExceptionContext) -> Result<u64, &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let parent = current(&mut processes).ok_or("no running user program")?;
+    let mut child = Box::new(Process::new(parent.address_space.fork()?));
+    child.ppid = parent.pid;
+    child.signals = parent.signals.fork();
+
+    let mut context = Box::new(e.clone());
+    context.gpr[0] = 0;
+    let fp_simd_enabled = fp_simd::is_enabled();
+    let pid = child.pid;
+    // the child thread cannot look its process up before it is added, `PROCESSES` is locked
+    child.tid = thread::spawn("fork", move || run_forked(pid, &context, fp_simd_enabled))?;
+    processes.push(child);
+
+    Ok(pid)
+}
+
+/// Run the forked program `pid` in the executing thread from `context` until it exits.
+fn run_forked(pid: u64, context: &ExceptionContext, fp_simd_enabled: bool) {
+    let kernel_context = {
+        let mut processes = PROCESSES.lock().unwrap();
+        let process = current(&mut processes).unwrap();
+        // SAFETY: The address space lives in `PROCESSES` until the user table is reset.
+        unsafe { process.address_space.activate() };
+        core::ptr::addr_of_mut!(process.kernel_context)
+    };
+
+    // the FP/SIMD registers in the context are only restored if the program owns the FPU
+    if fp_simd_enabled {
+        fp_simd::enable();
+    } else {
+        fp_simd::disable();
+    }
+
+    // SAFETY: The context is boxed in `PROCESSES` until the program exits.
+    let status = unsafe { __process_enter_user_context(kernel_context, context) };
+
+    memory::mmu::reset_user_table();
+    remove(pid, status);
+}
+
+/// Wait until a child of the running program exits, the child `pid` or any of them, and return
+/// its PID and exit status.
+///
+/// Fails without waiting for the child if the program receives `SIGKILL`.
+pub fn wait(pid: Option<u64>) -> Result<(u64, i32), &'static str> {
+    let ppid = current_pid().ok_or("no running user program")?;
+    let is_awaited = |child_pid: u64, child_ppid: u64| {
+        child_ppid == ppid && pid.map_or(true, |pid| pid == child_pid)
+    };
+
+    let mut result = Err("no child to wait for");
+    CHILD_EXIT.wait_until(|| {
+        let mut processes = PROCESSES.lock().unwrap();
+        if current(&mut processes).is_some_and(|process| process.signals.kill_pending()) {
+            result = Err("killed");
+            return true;
+        }
+        let mut zombies = ZOMBIES.lock().unwrap();
+        if let Some(index) = zombies
+            .iter()
+            .position(|zombie| is_awaited(zombie.pid, zombie.ppid))
+        {
+            let zombie = zombies.swap_remove(index);
+            result = Ok((zombie.pid, zombie.status));
+            return true;
+        }
+        // still running, unless there is no such child
+        !processes
+            .iter()
+            .any(|process| is_awaited(process.pid, process.ppid))
+    });
+    result
+}
+
+/// Replace the image of the running program with `program`.
+///
+/// The program gets a fresh address space and the registers are cleared, so it starts from its
+/// entry point once the exception returns. The old address space is freed.
+pub fn exec(e: &mut ExceptionContext, program: &CpioEntry<'static>) -> Result<(), &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+
+    let address_space = load_program(program)?;
+    // SAFETY: The old address space is only dropped once the new one is active.
+    unsafe { address_space.activate() };
+    process.address_space = address_space;
+    process.signals.reset_handlers();
+
+    e.gpr = [0; 30];
+    e.lr = 0;
+    e.elr_el1 = USER_CODE_START as u64;
+    e.sp_el0 = USER_STACK_END as u64;
+    e.tpidr_el0 = 0;
+    fp_simd::disable();
+
+    Ok(())
+}
+
+/// Terminate the running user program and resume the kernel flow that started it.
+pub fn exit(status: i32) -> ! {
+    let kernel_context = {
+        let mut processes = PROCESSES.lock().unwrap();
+        let process = current(&mut processes).expect("no running user program to exit");
+        core::ptr::addr_of!(process.kernel_context)
+    };
+
+    // SAFETY: The context was filled by `__process_enter_user` in `run`, or by
+    // `__process_enter_user_context` in `run_forked`.
+    unsafe { __process_leave_user(kernel_context, status) }
+}
+
+/// PID of the user program running in the executing thread.
+pub fn current_pid() -> Option<u64> {
+    let mut processes = PROCESSES.lock().unwrap();
+    current(&mut processes).map(|process| process.pid)
+}
+
+/// Resolve a fault of the running user program on `address`, see `AddressSpace::handle_fault`.
+pub fn handle_page_fault(address: usize, access: Access) -> Result<(), &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+    process.address_space.handle_fault(address, access)
+}
+
+/// Add an area to the running user program, see `AddressSpace::map_area`.
+pub fn mmap(
+    hint: usize,
+    len: usize,
+    prot: PageFlags,
+    backing: Backing,
+    fixed: bool,
+) -> Result<usize, &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+    process
+        .address_space
+        .map_area(hint, len, prot, backing, fixed)
+}
+
+/// Remove the areas of the running user program in `start..start + len`.
+pub fn munmap(start: usize, len: usize) -> Result<(), &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+    let end = start.checked_add(len).ok_or("invalid length")?;
+    process.address_space.unmap(start..end)
+}
+
+/// PIDs of the user programs of all threads.
+pub fn pids() -> Vec<u64> {
+    let processes = PROCESSES.lock().unwrap();
+    processes.iter().map(|process| process.pid).collect()
+}
+
+/// The areas of the user program `pid`.
+pub fn vmas(pid: u64) -> Option<Vec<Vma>> {
+    let processes = PROCESSES.lock().unwrap();
+    let process = processes.iter().find(|process| process.pid == pid)?;
+    Some(process.address_space.vmas().to_vec())
+}
+
+/// Map the pages of `start..start + len` in the running user program so that the kernel can
+/// `access` them, false if the program is not allowed to.
+pub fn fault_in_user_range(start: usize, len: usize, access: Access) -> bool {
+    let mut processes = PROCESSES.lock().unwrap();
+    current(&mut processes).is_some_and(|process| {
+        process
+            .address_space
+            .fault_in_range(start, len, access)
+            .is_ok()
+    })
+}
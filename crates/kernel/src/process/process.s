@@ -0,0 +1,131 @@
+// Offsets into `KernelContext`, see crates/kernel/src/process/mod.rs
+.equ KERNEL_CONTEXT_SP,   16 * 6
+.equ KERNEL_CONTEXT_DAIF, 16 * 6 + 8
+
+// Size of `ExceptionContext`, see crates/kernel/src/exception/handler.rs
+.equ EXCEPTION_CONTEXT_SIZE, 16 * 51
+
+// Save the callee-saved registers, sp and DAIF into the `KernelContext` at x0, then mask IRQs until
+// the eret. Clobbers x9, x10.
+.macro SAVE_KERNEL_CONTEXT
+    stp     x19, x20, [x0, #16 * 0]
+    stp     x21, x22, [x0, #16 * 1]
+    stp     x23, x24, [x0, #16 * 2]
+    stp     x25, x26, [x0, #16 * 3]
+    stp     x27, x28, [x0, #16 * 4]
+    stp     x29, lr,  [x0, #16 * 5]
+
+    mov     x9,  sp
+    mrs     x10, DAIF
+    stp     x9,  x10, [x0, #KERNEL_CONTEXT_SP]
+
+    // An IRQ would overwrite ELR_EL1 and SPSR_EL1, or switch threads, before the eret.
+    msr     DAIFSet, #0b0010
+.endm
+
+// fn __process_enter_user(
+//     kernel_context: *mut KernelContext, // x0
+//     entry: u64,                         // x1
+//     user_stack_end: u64,                // x2
+//     spsr: u64,                          // x3
+// ) -> i32
+//
+// Save the callee-saved registers of the caller into `kernel_context` and drop to EL0.
+// The call "returns" once `__process_leave_user` is called with the same context.
+__process_enter_user:
+    SAVE_KERNEL_CONTEXT
+
+    msr     ELR_EL1,  x1
+    msr     SP_EL0,   x2
+    msr     SPSR_EL1, x3
+    msr     TPIDR_EL0, xzr
+
+    // Do not leak kernel values into the user program.
+    mov     x0,  xzr
+    mov     x1,  xzr
+    mov     x2,  xzr
+    mov     x3,  xzr
+    mov     x4,  xzr
+    mov     x5,  xzr
+    mov     x6,  xzr
+    mov     x7,  xzr
+    mov     x8,  xzr
+    mov     x9,  xzr
+    mov     x10, xzr
+    mov     x11, xzr
+    mov     x12, xzr
+    mov     x13, xzr
+    mov     x14, xzr
+    mov     x15, xzr
+    mov     x16, xzr
+    mov     x17, xzr
+    mov     x18, xzr
+    mov     x19, xzr
+    mov     x20, xzr
+    mov     x21, xzr
+    mov     x22, xzr
+    mov     x23, xzr
+    mov     x24, xzr
+    mov     x25, xzr
+    mov     x26, xzr
+    mov     x27, xzr
+    mov     x28, xzr
+    mov     x29, xzr
+    mov     lr,  xzr
+
+    // *return* to EL0
+    eret
+
+.size   __process_enter_user, . - __process_enter_user
+.type   __process_enter_user, function
+.global __process_enter_user
+
+// fn __process_enter_user_context(
+//     kernel_context: *mut KernelContext, // x0
+//     context: *const ExceptionContext,   // x1
+// ) -> i32
+//
+// Like `__process_enter_user`, but drop to EL0 with all the registers of `context`, as if
+// returning from the exception it was saved by.
+__process_enter_user_context:
+    SAVE_KERNEL_CONTEXT
+
+    // `__exception_restore_context` expects the context on the stack
+    sub     sp,  sp, #EXCEPTION_CONTEXT_SIZE
+    mov     x2,  sp
+    mov     x3,  #EXCEPTION_CONTEXT_SIZE
+1:
+    ldp     x4,  x5, [x1], #16
+    stp     x4,  x5, [x2], #16
+    subs    x3,  x3, #16
+    b.ne    1b
+
+    b       __exception_restore_context
+
+.size   __process_enter_user_context, . - __process_enter_user_context
+.type   __process_enter_user_context, function
+.global __process_enter_user_context
+
+// fn __process_leave_user(kernel_context: *const KernelContext, status: i32) -> !
+//
+// Restore the context saved by `__process_enter_user`, making it return `status`.
+__process_leave_user:
+    ldp     x19, x20, [x0, #16 * 0]
+    ldp     x21, x22, [x0, #16 * 1]
+    ldp     x23, x24, [x0, #16 * 2]
+    ldp     x25, x26, [x0, #16 * 3]
+    ldp     x27, x28, [x0, #16 * 4]
+    ldp     x29, lr,  [x0, #16 * 5]
+
+    ldp     x9,  x10, [x0, #KERNEL_CONTEXT_SP]
+    mov     sp,  x9
+    msr     DAIF, x10
+
+    mov     w0,  w1
+    ret
+
+.size   __process_leave_user, . - __process_leave_user
+.type   __process_leave_user, function
+.global __process_leave_user
+
+// vim: ft=asm
@@ -0,0 +1,342 @@
+//! POSIX-style signals.
+//!
+//! A signal sent to a process is recorded as pending, and delivered the next time the process
+//! returns to EL0 unless it is blocked. Its action is either the default one, to terminate or to
+//! ignore, or a handler registered by the program. A handler runs on the user stack, below a
+//! `SignalFrame` holding the interrupted registers, and returns to the sigreturn trampoline which
+//! asks the kernel to restore them.
+
+use core::mem::size_of;
+
+use crate::{
+    exception::ExceptionContext,
+    memory::{Access, Backing, PageFlags, Vma, PAGE_SIZE},
+    syscall::SYS_SIGRETURN,
+    thread,
+};
+
+use super::{current, fault_in_user_range, PROCESSES};
+
+/// Signals are numbered from 1 up to `NSIG - 1`, as on Linux.
+pub const NSIG: usize = 32;
+
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+/// Where the trampoline handlers return to is mapped, the last page of the user space.
+const SIGRETURN_TRAMPOLINE_START: usize = 0x0000_ffff_ffff_f000;
+
+/// `mov x8, #SYS_SIGRETURN; svc #0`
+static SIGRETURN_TRAMPOLINE: [u8; 8] = {
+    let mov = (0xd280_0008 | ((SYS_SIGRETURN as u32) << 5)).to_le_bytes();
+    let svc = 0xd400_0001u32.to_le_bytes();
+    [
+        mov[0], mov[1], mov[2], mov[3], svc[0], svc[1], svc[2], svc[3],
+    ]
+};
+
+/// The area of the trampoline, mapped in every user program.
+pub(super) fn sigreturn_trampoline_vma() -> Vma {
+    Vma {
+        range: SIGRETURN_TRAMPOLINE_START..SIGRETURN_TRAMPOLINE_START + PAGE_SIZE,
+        prot: PageFlags {
+            readable: true,
+            writable: false,
+            executable: true,
+        },
+        backing: Backing::File {
+            name: "[sigreturn]",
+            content: &SIGRETURN_TRAMPOLINE,
+            offset: 0,
+        },
+    }
+}
+
+/// What a process does when it receives a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigAction {
+    /// `SIG_DFL`, see `default_terminates`.
+    Default,
+    /// `SIG_IGN`
+    Ignore,
+    /// Call the user function at this address.
+    Handler(usize),
+}
+
+impl SigAction {
+    /// The action from the `handler` argument of the `signal` system call.
+    pub fn from_user(handler: usize) -> Self {
+        match handler {
+            0 => Self::Default,
+            1 => Self::Ignore,
+            address => Self::Handler(address),
+        }
+    }
+
+    /// The value the `signal` system call returns for the action.
+    pub fn to_user(self) -> usize {
+        match self {
+            Self::Default => 0,
+            Self::Ignore => 1,
+            Self::Handler(address) => address,
+        }
+    }
+}
+
+/// How `sigprocmask` changes the blocked signals.
+#[derive(Debug, Clone, Copy)]
+pub enum MaskHow {
+    Block,
+    Unblock,
+    SetMask,
+}
+
+/// Signal state of a process, the masks have bit `n` set for signal `n`.
+#[derive(Debug, Clone)]
+pub struct Signals {
+    pending: u64,
+    blocked: u64,
+    actions: [SigAction; NSIG],
+}
+
+/// Signals that can neither be caught, ignored nor blocked.
+const UNCATCHABLE: u64 = (1 << SIGKILL) | (1 << SIGSTOP);
+
+/// Registers saved on the user stack while a handler runs.
+#[repr(C)]
+struct SignalFrame {
+    context: ExceptionContext,
+    /// Blocked signals before the handler was entered.
+    blocked: u64,
+}
+
+/// Whether the default action of `signal` is to terminate the process, it is ignored otherwise.
+///
+/// Processes cannot be stopped, so `SIGSTOP` terminates them.
+fn default_terminates(signal: usize) -> bool {
+    !matches!(signal, SIGCHLD | SIGCONT | SIGURG | SIGWINCH)
+}
+
+fn check_signal(signal: usize) -> Result<(), &'static str> {
+    if (1..NSIG).contains(&signal) {
+        Ok(())
+    } else {
+        Err("invalid signal")
+    }
+}
+
+impl Signals {
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::Default; NSIG],
+        }
+    }
+
+    /// State of the child of a fork, which inherits the actions and blocked signals, but none of
+    /// the pending ones.
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..self.clone()
+        }
+    }
+
+    /// Reset the handlers after an exec, their code is gone. Ignored signals stay ignored.
+    pub fn reset_handlers(&mut self) {
+        for action in &mut self.actions {
+            if let SigAction::Handler(_) = action {
+                *action = SigAction::Default;
+            }
+        }
+    }
+
+    /// Mark `signal` pending, it is delivered once the process returns to EL0.
+    pub fn raise(&mut self, signal: usize) -> Result<(), &'static str> {
+        check_signal(signal)?;
+        self.pending |= 1 << signal;
+        Ok(())
+    }
+
+    /// Set the action of `signal`, returning the previous one.
+    pub fn set_action(
+        &mut self,
+        signal: usize,
+        action: SigAction,
+    ) -> Result<SigAction, &'static str> {
+        check_signal(signal)?;
+        if UNCATCHABLE & (1 << signal) != 0 {
+            return Err("signal cannot be caught or ignored");
+        }
+        Ok(core::mem::replace(&mut self.actions[signal], action))
+    }
+
+    /// Whether `SIGKILL` is pending, which ends any wait of the process.
+    pub fn kill_pending(&self) -> bool {
+        self.pending & (1 << SIGKILL) != 0
+    }
+
+    /// Change the blocked signals, returning the previous mask.
+    pub fn set_blocked(&mut self, how: MaskHow, mask: u64) -> u64 {
+        let previous = self.blocked;
+        let blocked = match how {
+            MaskHow::Block => previous | mask,
+            MaskHow::Unblock => previous & !mask,
+            MaskHow::SetMask => mask,
+        };
+        // bit 0 is not a signal
+        self.blocked = blocked & !UNCATCHABLE & !1;
+        previous
+    }
+
+    /// Take the lowest pending signal that is not blocked, with its action.
+    fn take_deliverable(&mut self) -> Option<(usize, SigAction)> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+
+        let signal = deliverable.trailing_zeros() as usize;
+        self.pending &= !(1 << signal);
+        Some((signal, self.actions[signal]))
+    }
+}
+
+/// Exit status of a process terminated by `signal`, what shells report.
+pub const fn signal_exit_status(signal: usize) -> i32 {
+    128 + signal as i32
+}
+
+/// Send `signal` to the user program `pid`. Signal 0 only checks that it exists.
+///
+/// A program blocked in a system call is woken by `SIGKILL`, and terminated on its way back to
+/// EL0.
+pub fn kill(pid: u64, signal: usize) -> Result<(), &'static str> {
+    let tid = {
+        let mut processes = PROCESSES.lock().unwrap();
+        let process = processes
+            .iter_mut()
+            .find(|process| process.pid == pid)
+            .ok_or("no such process")?;
+        if signal == 0 {
+            return Ok(());
+        }
+        process.signals.raise(signal)?;
+        process.tid
+    };
+
+    if signal == SIGKILL {
+        thread::wake(tid);
+    }
+    Ok(())
+}
+
+/// Whether `SIGKILL` is pending for the running user program, which must stop blocking.
+pub fn kill_pending() -> bool {
+    let mut processes = PROCESSES.lock().unwrap();
+    current(&mut processes).is_some_and(|process| process.signals.kill_pending())
+}
+
+/// Set the action of `signal` for the running user program, returning the previous one.
+pub fn set_action(signal: usize, action: SigAction) -> Result<SigAction, &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+    process.signals.set_action(signal, action)
+}
+
+/// Change the blocked signals of the running user program, returning the previous mask.
+pub fn set_blocked(how: MaskHow, mask: u64) -> Result<u64, &'static str> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = current(&mut processes).ok_or("no running user program")?;
+    Ok(process.signals.set_blocked(how, mask))
+}
+
+/// Deliver the pending signals of the running user program before it returns to EL0 with `e`.
+///
+/// Ignored signals are dropped, and a signal whose default action is to terminate ends the
+/// program. A handler is entered by redirecting `e` to it, the other signals wait until it
+/// returns.
+pub fn deliver_pending(e: &mut ExceptionContext) {
+    loop {
+        let (signal, action, blocked) = {
+            let mut processes = PROCESSES.lock().unwrap();
+            let Some(process) = current(&mut processes) else {
+                return;
+            };
+            let Some((signal, action)) = process.signals.take_deliverable() else {
+                return;
+            };
+            (signal, action, process.signals.blocked)
+        };
+
+        match action {
+            SigAction::Ignore => {}
+            SigAction::Default if !default_terminates(signal) => {}
+            SigAction::Default => super::exit(signal_exit_status(signal)),
+            SigAction::Handler(handler) => {
+                if enter_handler(e, signal, handler, blocked).is_err() {
+                    // the program cannot be resumed in its handler
+                    super::exit(signal_exit_status(SIGSEGV));
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Save `e` in a frame on the user stack and redirect it to `handler`, blocking `signal` until
+/// the handler returns.
+fn enter_handler(
+    e: &mut ExceptionContext,
+    signal: usize,
+    handler: usize,
+    blocked: u64,
+) -> Result<(), &'static str> {
+    let frame_addr = (e.sp_el0 as usize)
+        .checked_sub(size_of::<SignalFrame>())
+        .ok_or("user stack overflow")?
+        & !0xf;
+    if !fault_in_user_range(frame_addr, size_of::<SignalFrame>(), Access::Write) {
+        return Err("user stack overflow");
+    }
+
+    let frame = SignalFrame {
+        context: e.user_copy(),
+        blocked,
+    };
+    // SAFETY: The frame is mapped writable in the active user address space, and aligned.
+    unsafe { (frame_addr as *mut SignalFrame).write(frame) };
+
+    set_blocked(MaskHow::Block, 1 << signal)?;
+
+    e.gpr[0] = signal as u64;
+    e.lr = SIGRETURN_TRAMPOLINE_START as u64;
+    e.elr_el1 = handler as u64;
+    e.sp_el0 = frame_addr as u64;
+    Ok(())
+}
+
+/// Restore the registers and blocked signals saved when the handler that returned to the
+/// trampoline was entered.
+pub fn sigreturn(e: &mut ExceptionContext) -> Result<(), &'static str> {
+    // the handler returns with the stack pointer it was entered with, pointing to the frame
+    let frame_addr = e.sp_el0 as usize;
+    if frame_addr % 16 != 0
+        || !fault_in_user_range(frame_addr, size_of::<SignalFrame>(), Access::Read)
+    {
+        return Err("invalid signal frame");
+    }
+
+    // SAFETY: The frame is mapped in the active user address space, and aligned.
+    let frame = unsafe { (frame_addr as *const SignalFrame).read() };
+    e.restore_user_context(&frame.context);
+    set_blocked(MaskHow::SetMask, frame.blocked)?;
+    Ok(())
+}
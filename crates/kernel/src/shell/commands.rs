@@ -1,6 +1,17 @@
+use core::time::Duration;
+
 use super::ShellCommand;
-use crate::{cpio::CpioArchive, driver, exception};
-use alloc::boxed::Box;
+use crate::{
+    allocator,
+    cpio::{CpioArchive, CpioEntry},
+    driver, exception,
+    memory::{self, Backing},
+    process::{self, signal},
+    smp, symbols,
+    thread::{self, ThreadState},
+    timer,
+};
+use alloc::{string::ToString, vec};
 use small_std::{print, println};
 
 pub struct Hello;
@@ -64,6 +75,347 @@ impl ShellCommand for Info {
     }
 }
 
+pub struct Uptime;
+
+impl ShellCommand for Uptime {
+    fn name(&self) -> &str {
+        "uptime"
+    }
+
+    fn help(&self) -> &str {
+        "print the time since boot"
+    }
+
+    fn execute(&self, _: &str) {
+        let core_timer = driver::core_timer();
+        let uptime = core_timer.uptime();
+        println!(
+            "up {}.{:03} seconds ({} ticks)",
+            uptime.as_secs(),
+            uptime.subsec_millis(),
+            core_timer.ticks()
+        );
+    }
+}
+
+pub struct SetTimeout;
+
+impl ShellCommand for SetTimeout {
+    fn name(&self) -> &str {
+        "setTimeout"
+    }
+
+    fn help(&self) -> &str {
+        "setTimeout <message> <seconds>\tprint the message after the given seconds"
+    }
+
+    fn execute(&self, args: &str) {
+        let args = args.trim();
+        let parsed = args.rsplit_once(' ').and_then(|(message, seconds)| {
+            let seconds = seconds.parse::<u64>().ok()?;
+            Some((message.trim().to_string(), seconds))
+        });
+        let Some((message, seconds)) = parsed else {
+            println!("Usage: {} <message> <seconds>", self.name());
+            return;
+        };
+
+        let command_time = driver::core_timer().uptime();
+        timer::set_timeout(Duration::from_secs(seconds), move || {
+            let current_time = driver::core_timer().uptime();
+            println!(
+                "\n[{}.{:03}] {} (command time: {}.{:03})",
+                current_time.as_secs(),
+                current_time.subsec_millis(),
+                message,
+                command_time.as_secs(),
+                command_time.subsec_millis()
+            );
+        });
+    }
+}
+
+pub struct RunOn;
+
+impl ShellCommand for RunOn {
+    fn name(&self) -> &str {
+        "runon"
+    }
+
+    fn help(&self) -> &str {
+        "runon <core>\tprint a greeting from the given core"
+    }
+
+    fn execute(&self, args: &str) {
+        let Ok(core_id) = args.trim().parse::<usize>() else {
+            println!("Usage: {} <core>", self.name());
+            return;
+        };
+
+        let result = smp::run_on(core_id, || {
+            let (_, privilege_level) = exception::current_privilege_level();
+            println!(
+                "\nHello from core {} (privilege level: {}, ticks: {})",
+                smp::this_cpu().core_id(),
+                privilege_level,
+                driver::core_timer().ticks()
+            );
+        });
+        if let Err(e) = result {
+            println!("Failed to run on core {}: {}", core_id, e);
+        }
+    }
+}
+
+pub struct Pages;
+
+impl ShellCommand for Pages {
+    fn name(&self) -> &str {
+        "pages"
+    }
+
+    fn help(&self) -> &str {
+        "pages [alloc <order> | free <address> | debug <on|off>]\tinspect the page allocator"
+    }
+
+    fn execute(&self, args: &str) {
+        let mut args = args.split_whitespace();
+        match (args.next(), args.next()) {
+            (None, _) => {
+                for (order, count) in memory::free_blocks().iter().enumerate() {
+                    println!("order {:2}: {} free blocks", order, count);
+                }
+            }
+            (Some("alloc"), Some(order)) => {
+                let Ok(order) = order.parse::<usize>() else {
+                    println!("Invalid order: {}", order);
+                    return;
+                };
+                match memory::alloc_pages(order) {
+                    Some(address) => {
+                        println!("Allocated {} pages at {:#x}", 1usize << order, address)
+                    }
+                    None => println!("Failed to allocate an order {} block", order),
+                }
+            }
+            (Some("free"), Some(address)) => {
+                let address = address.trim_start_matches("0x");
+                let Ok(address) = usize::from_str_radix(address, 16) else {
+                    println!("Invalid address: {}", address);
+                    return;
+                };
+                if let Err(e) = memory::free_pages(address) {
+                    println!("Failed to free {:#x}: {}", address, e);
+                }
+            }
+            (Some("debug"), Some("on")) => memory::set_debug(true),
+            (Some("debug"), Some("off")) => memory::set_debug(false),
+            _ => println!(
+                "Usage: {} [alloc <order> | free <address> | debug <on|off>]",
+                self.name()
+            ),
+        }
+    }
+}
+
+pub struct MemInfo;
+
+impl ShellCommand for MemInfo {
+    fn name(&self) -> &str {
+        "meminfo"
+    }
+
+    fn help(&self) -> &str {
+        "meminfo [track <on|off> | live]\tprint heap usage, or track the live allocations"
+    }
+
+    fn execute(&self, args: &str) {
+        let mut args = args.split_whitespace();
+        match (args.next(), args.next()) {
+            (None, _) => self.print_stats(),
+            (Some("track"), Some("on")) => allocator::set_allocation_tracking(true),
+            (Some("track"), Some("off")) => allocator::set_allocation_tracking(false),
+            (Some("live"), None) => self.print_live_allocations(),
+            _ => println!("Usage: {} [track <on|off> | live]", self.name()),
+        }
+    }
+}
+
+impl MemInfo {
+    fn print_stats(&self) {
+        let stats = allocator::heap_stats();
+        let arena = allocator::heap_range();
+
+        println!(
+            "Heap arena: {} / {} KiB used",
+            allocator::heap_arena_used() / 1024,
+            arena.len() / 1024
+        );
+        println!(
+            "In use: {} bytes (peak: {} bytes)",
+            stats.bytes_in_use, stats.peak_bytes_in_use
+        );
+        println!("Failed allocations: {}", stats.failed_allocations);
+        if let Some(layout) = stats.last_failed_layout {
+            println!(
+                "  last: {} bytes aligned to {}",
+                layout.size(),
+                layout.align()
+            );
+        }
+        println!(
+            "Free pages: {} ({} KiB)",
+            memory::num_free_pages(),
+            memory::num_free_pages() * memory::PAGE_SIZE / 1024
+        );
+
+        println!(
+            "{:>8} {:>10} {:>10} {:>10}",
+            "class", "allocs", "frees", "live"
+        );
+        let print_source = |name: &dyn core::fmt::Display, source: &allocator::SourceStats| {
+            println!(
+                "{:>8} {:>10} {:>10} {:>10}",
+                name,
+                source.allocations,
+                source.frees,
+                source.live()
+            );
+        };
+        for (class, source) in stats.objects.iter().enumerate() {
+            print_source(&(memory::MIN_OBJECT_SIZE << class), source);
+        }
+        print_source(&"pages", &stats.pages);
+        print_source(&"early", &stats.early);
+    }
+
+    fn print_live_allocations(&self) {
+        if !allocator::is_allocation_tracking() {
+            println!("Allocation tracking is off, enable it with `track on`");
+            return;
+        }
+
+        let tracker = allocator::allocation_tracker();
+        for allocation in tracker.iter() {
+            print!(
+                "{:#016x} {:>8} bytes from {:#016x}",
+                allocation.address, allocation.size, allocation.caller
+            );
+            match symbols::resolve(allocation.caller) {
+                Some(symbol) => println!(" - {}", symbol),
+                None => println!(),
+            }
+        }
+        if tracker.untracked > 0 {
+            println!(
+                "{} allocations did not fit in the record",
+                tracker.untracked
+            );
+        }
+    }
+}
+
+pub struct Maps;
+
+impl ShellCommand for Maps {
+    fn name(&self) -> &str {
+        "maps"
+    }
+
+    fn help(&self) -> &str {
+        "maps [pid]\t\tprint the memory areas of the user programs"
+    }
+
+    fn execute(&self, args: &str) {
+        let pids = match args.split_whitespace().next() {
+            None => process::pids(),
+            Some(pid) => match pid.parse() {
+                Ok(pid) => vec![pid],
+                Err(_) => {
+                    println!("Invalid PID: {}", pid);
+                    return;
+                }
+            },
+        };
+
+        for pid in pids {
+            let Some(vmas) = process::vmas(pid) else {
+                println!("{}: {}: No such process", self.name(), pid);
+                continue;
+            };
+
+            println!("PID {}:", pid);
+            for vma in vmas {
+                let (offset, name) = match vma.backing {
+                    Backing::Anonymous => (0, ""),
+                    Backing::File { name, offset, .. } => (offset, name),
+                };
+                println!(
+                    "{:012x}-{:012x} {}p {:08x} {}",
+                    vma.range.start, vma.range.end, vma.prot, offset, name
+                );
+            }
+        }
+    }
+}
+
+pub struct Kill;
+
+impl ShellCommand for Kill {
+    fn name(&self) -> &str {
+        "kill"
+    }
+
+    fn help(&self) -> &str {
+        "kill [-<signal>] <pid>\tsend a signal to a user program, SIGTERM by default"
+    }
+
+    fn execute(&self, args: &str) {
+        let mut args = args.split_whitespace();
+        let (signal, pid) = match (args.next(), args.next(), args.next()) {
+            (Some(pid), None, None) => (Some(signal::SIGTERM), pid.parse().ok()),
+            (Some(signal), Some(pid), None) => (
+                signal.strip_prefix('-').and_then(|s| s.parse().ok()),
+                pid.parse().ok(),
+            ),
+            _ => (None, None),
+        };
+        let (Some(signal), Some(pid)) = (signal, pid) else {
+            println!("Usage: {} [-<signal>] <pid>", self.name());
+            return;
+        };
+
+        if let Err(e) = signal::kill(pid, signal) {
+            println!("{}: {}: {}", self.name(), pid, e);
+        }
+    }
+}
+
+pub struct Threads;
+
+impl ShellCommand for Threads {
+    fn name(&self) -> &str {
+        "threads"
+    }
+
+    fn help(&self) -> &str {
+        "threads\t\t\tlist the kernel threads"
+    }
+
+    fn execute(&self, _args: &str) {
+        println!("{:>5} {:<8} name", "tid", "state");
+        for thread in thread::threads() {
+            let state = match thread.state {
+                ThreadState::Ready => "ready",
+                ThreadState::Running => "running",
+                ThreadState::Blocked => "blocked",
+                ThreadState::Zombie => "zombie",
+            };
+            println!("{:>5} {:<8} {}", thread.tid, state, thread.name);
+        }
+    }
+}
+
 pub struct Ls<'a> {
     cpio: &'a CpioArchive,
 }
@@ -145,14 +497,16 @@ impl<'a> ShellCommand for Exec<'a> {
     }
 
     fn help(&self) -> &str {
-        "exec <program>\t\texecute the program in the initramfs"
+        "exec <program> [&]\texecute the program in the initramfs, in the background with &"
     }
 
     fn execute(&self, args: &str) {
-        let filename = match args.split_whitespace().next() {
-            Some(filename) => filename,
-            None => {
-                println!("Usage: {} <program>", self.name());
+        let mut args = args.split_whitespace();
+        let (filename, background) = match (args.next(), args.next()) {
+            (Some(filename), None) => (filename, false),
+            (Some(filename), Some("&")) => (filename, true),
+            _ => {
+                println!("Usage: {} <program> [&]", self.name());
                 return;
             }
         };
@@ -165,10 +519,23 @@ impl<'a> ShellCommand for Exec<'a> {
             }
         };
 
-        let stack = Box::new([0u8; 0x1000]);
-        let stack_end = stack.as_ptr() as u64 + 0x1000;
-        unsafe {
-            exception::transition_from_el1_to_el0(stack_end, program.content.as_ptr() as u64);
+        if !background {
+            run_program(&program);
+            return;
+        }
+
+        match thread::spawn(program.filename, move || run_program(&program)) {
+            Ok(tid) => println!("[{}] {}", tid, filename),
+            Err(e) => println!("{}: {}: {}", self.name(), filename, e),
         }
     }
 }
+
+/// Run `program` in the executing thread and report how it ended, on behalf of `Exec`.
+fn run_program(program: &CpioEntry<'static>) {
+    match process::run(program) {
+        Ok(0) => {}
+        Ok(status) => println!("exec: {}: exited with status {}", program.filename, status),
+        Err(e) => println!("exec: {}: {}", program.filename, e),
+    }
+}
@@ -1,7 +1,9 @@
 pub mod commands;
 
 use alloc::{string::String, vec::Vec};
-use small_std::{fmt::print::console::console, print, println};
+use small_std::{print, println};
+
+use crate::driver;
 
 pub trait ShellCommand {
     fn name(&self) -> &str;
@@ -40,7 +42,8 @@ impl<'a> Shell<'a> {
 
     fn read_input(&mut self) {
         loop {
-            let c = console().read_char();
+            // the shell thread stays blocked until input arrives
+            let c = driver::console_read_char();
 
             match c {
                 '\r' | '\n' => {
@@ -0,0 +1,186 @@
+//! Secondary core bring-up and per-CPU data.
+//!
+//! The firmware parks the secondary cores in a loop waiting for an entry address at their spin
+//! table release address. Once released, each core transitions to EL1 on its own stack and waits
+//! for work submitted with `run_on`.
+//!
+//! A released core runs with its MMU and data cache off until it reaches EL1, so everything it
+//! reads before is cleaned to memory first. From then on the caches are coherent between the
+//! cores, the firmware stub sets CPUECTLR_EL1.SMPEN before parking them.
+
+use core::{
+    alloc::Layout,
+    arch::asm,
+    cell::UnsafeCell,
+    mem::{size_of, size_of_val},
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use aarch64_cpu::registers::TPIDR_EL1;
+use alloc::{boxed::Box, collections::VecDeque};
+use device::cpu::{self, core_id, NUM_CORES};
+use small_std::sync::IRQSafeMutex;
+use tock_registers::interfaces::{Readable, Writeable};
+
+use crate::{boot, driver, memory, thread};
+
+const SECONDARY_CORE_STACK_SIZE: usize = 0x10000;
+
+/// Where the firmware spin table expects the physical entry address of each core.
+const SPIN_TABLE_RELEASE_ADDRS: [usize; NUM_CORES] = [0xd8, 0xe0, 0xe8, 0xf0];
+
+/// How long to wait for the secondary cores to come online.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Work = Box<dyn FnOnce() + Send>;
+
+/// Data owned by a core, reached through TPIDR_EL1.
+pub struct PerCpu {
+    core_id: usize,
+    online: AtomicBool,
+    work: IRQSafeMutex<VecDeque<Work>>,
+}
+
+static PER_CPU: [PerCpu; NUM_CORES] = [
+    PerCpu::new(0),
+    PerCpu::new(1),
+    PerCpu::new(2),
+    PerCpu::new(3),
+];
+
+/// Stack of each secondary core, read by `_start_secondary` in boot.s.
+#[no_mangle]
+static SECONDARY_CORE_STACK_ENDS: [AtomicUsize; NUM_CORES] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+impl PerCpu {
+    const fn new(core_id: usize) -> Self {
+        Self {
+            core_id,
+            online: AtomicBool::new(false),
+            work: IRQSafeMutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn core_id(&self) -> usize {
+        self.core_id
+    }
+
+    fn is_online(&self) -> bool {
+        self.online.load(Ordering::Acquire)
+    }
+}
+
+/// Point TPIDR_EL1 at the data of the executing core and mark it online.
+///
+/// # Safety
+///
+/// - Must be called once on each core, before `this_cpu`
+pub unsafe fn init_this_cpu() {
+    let cpu = &PER_CPU[core_id()];
+    TPIDR_EL1.set(cpu as *const PerCpu as u64);
+    cpu.online.store(true, Ordering::Release);
+}
+
+/// Data of the executing core.
+pub fn this_cpu() -> &'static PerCpu {
+    // SAFETY: TPIDR_EL1 is set up by `init_this_cpu` and is not touched anywhere else.
+    unsafe { &*(TPIDR_EL1.get() as *const PerCpu) }
+}
+
+/// Bounds of the kernel stack of the executing core, or of the running thread on the boot core.
+pub fn current_stack_range() -> Range<usize> {
+    match core_id() {
+        0 => thread::current_stack_range().unwrap_or_else(boot::boot_core_stack_range),
+        core => {
+            let end = SECONDARY_CORE_STACK_ENDS[core].load(Ordering::Relaxed);
+            end - SECONDARY_CORE_STACK_SIZE..end
+        }
+    }
+}
+
+/// Release the secondary cores from the spin table, and return the number of online cores.
+///
+/// # Safety
+///
+/// - Must only be called once, by the boot core
+pub unsafe fn start_secondary_cores() -> usize {
+    extern "Rust" {
+        static _start_secondary: UnsafeCell<()>;
+    }
+
+    let layout = Layout::from_size_align(SECONDARY_CORE_STACK_SIZE, 16).unwrap();
+    for core in 1..NUM_CORES {
+        let stack = alloc::alloc::alloc(layout);
+        if stack.is_null() {
+            continue;
+        }
+        SECONDARY_CORE_STACK_ENDS[core].store(
+            stack as usize + SECONDARY_CORE_STACK_SIZE,
+            Ordering::Release,
+        );
+        // the core uses its stack with the MMU, and so the data cache, still off: dirty lines of
+        // a previous owner must not be written back over it later
+        cpu::clean_invalidate_dcache_range(stack as usize, SECONDARY_CORE_STACK_SIZE);
+
+        // the cores start with the MMU off, at the physical address
+        let release_addr = memory::phys_to_virt(SPIN_TABLE_RELEASE_ADDRS[core]) as *mut u64;
+        release_addr.write_volatile(memory::virt_to_phys(_start_secondary.get() as usize) as u64);
+        cpu::clean_invalidate_dcache_range(release_addr as usize, size_of::<u64>());
+    }
+    cpu::clean_invalidate_dcache_range(
+        SECONDARY_CORE_STACK_ENDS.as_ptr() as usize,
+        size_of_val(&SECONDARY_CORE_STACK_ENDS),
+    );
+
+    // the cores read the release addresses and their stacks from memory, not from the caches
+    asm!("dsb sy", "sev", options(nostack));
+
+    let core_timer = driver::core_timer();
+    let deadline = core_timer.uptime() + STARTUP_TIMEOUT;
+    while !PER_CPU.iter().all(PerCpu::is_online) && core_timer.uptime() < deadline {
+        core::hint::spin_loop();
+    }
+
+    PER_CPU.iter().filter(|cpu| cpu.is_online()).count()
+}
+
+/// Run `work` on core `core_id`.
+///
+/// Returns as soon as the work is queued, the core runs it once it is idle.
+pub fn run_on<F>(core_id: usize, work: F) -> Result<(), &'static str>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let cpu = PER_CPU.get(core_id).ok_or("no such core")?;
+    if !cpu.is_online() {
+        return Err("core is not online");
+    }
+    if cpu.core_id == this_cpu().core_id {
+        return Err("cannot queue work for the executing core");
+    }
+
+    cpu.work.lock().unwrap().push_back(Box::new(work));
+    unsafe { asm!("dsb sy", "sev", options(nostack)) };
+
+    Ok(())
+}
+
+/// Idle loop of the secondary cores, running the work submitted to them.
+pub fn secondary_core_main() -> ! {
+    let cpu = this_cpu();
+    loop {
+        let work = cpu.work.lock().unwrap().pop_front();
+        match work {
+            Some(work) => work(),
+            // `run_on` signals an event after queueing, so no work is missed in between
+            None => unsafe { asm!("wfe", options(nomem, nostack)) },
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Kernel symbol table, appended to the image by `xtask build`.
+//!
+//! The linker script reserves a region for the table right after `.data`. Images built without
+//! `xtask` leave it uninitialized, in which case no address can be resolved.
+
+use core::{cell::UnsafeCell, ops::Range};
+
+const MAGIC: &[u8; 4] = b"KSYM";
+
+#[repr(C)]
+struct Header {
+    magic: [u8; 4],
+    num_entries: u32,
+}
+
+#[repr(C)]
+struct Entry {
+    address: u64,
+    size: u32,
+    name_offset: u32,
+    name_len: u32,
+    _reserved: u32,
+}
+
+/// A function symbol and an offset into it.
+pub struct Symbol {
+    pub name: &'static str,
+    pub offset: usize,
+}
+
+struct SymbolTable {
+    region: Range<usize>,
+    entries: &'static [Entry],
+}
+
+impl core::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+impl SymbolTable {
+    fn get() -> Option<Self> {
+        extern "Rust" {
+            static __kernel_symbols_start: UnsafeCell<()>;
+            static __kernel_symbols_end_exclusive: UnsafeCell<()>;
+        }
+
+        let region = unsafe {
+            __kernel_symbols_start.get() as usize..__kernel_symbols_end_exclusive.get() as usize
+        };
+
+        let header = unsafe { &*(region.start as *const Header) };
+        let entries_start = region.start + core::mem::size_of::<Header>();
+        let entries_size = (header.num_entries as usize).checked_mul(core::mem::size_of::<Entry>());
+        if &header.magic != MAGIC || entries_size? > region.end - entries_start {
+            return None;
+        }
+
+        let entries = unsafe {
+            core::slice::from_raw_parts(entries_start as *const Entry, header.num_entries as usize)
+        };
+        Some(Self { region, entries })
+    }
+
+    fn name(&self, entry: &Entry) -> Option<&'static str> {
+        let start = self.region.start.checked_add(entry.name_offset as usize)?;
+        let end = start.checked_add(entry.name_len as usize)?;
+        if end > self.region.end {
+            return None;
+        }
+
+        let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+        core::str::from_utf8(bytes).ok()
+    }
+}
+
+/// Find the function containing `address`.
+pub fn resolve(address: usize) -> Option<Symbol> {
+    let table = SymbolTable::get()?;
+
+    let index = table
+        .entries
+        .partition_point(|entry| entry.address as usize <= address)
+        .checked_sub(1)?;
+    let entry = &table.entries[index];
+
+    let offset = address - entry.address as usize;
+    if entry.size != 0 && offset >= entry.size as usize {
+        return None;
+    }
+
+    Some(Symbol {
+        name: table.name(entry)?,
+        offset,
+    })
+}
@@ -0,0 +1,298 @@
+use core::{ffi::CStr, mem::size_of};
+
+use alloc::boxed::Box;
+use small_std::fmt::print::console::console;
+
+use super::SYSCALL_FAILED;
+use crate::{
+    cpio::CpioEntry,
+    driver,
+    exception::ExceptionContext,
+    memory::{Access, Backing, PageFlags, PAGE_SIZE},
+    process::{
+        self,
+        signal::{self, MaskHow, SigAction},
+    },
+};
+
+/// Longest string, terminator included, accepted from a user program.
+const MAX_USER_STRING_LEN: usize = 256;
+
+/// Largest mailbox message accepted from a user program.
+const MAX_MBOX_MESSAGE_SIZE: usize = 1024;
+
+// `mmap` protection and flags, with the values of Linux.
+const PROT_READ: u64 = 0x1;
+const PROT_WRITE: u64 = 0x2;
+const PROT_EXEC: u64 = 0x4;
+const MAP_PRIVATE: u64 = 0x02;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+// `sigprocmask` operations, with the values of Linux.
+const SIG_BLOCK: u64 = 0;
+const SIG_UNBLOCK: u64 = 1;
+const SIG_SETMASK: u64 = 2;
+
+/// Kernel copy of a mailbox message, aligned as the VideoCore requires.
+#[repr(C, align(16))]
+struct MboxMessage([u8; MAX_MBOX_MESSAGE_SIZE]);
+
+/// The user buffer at `address`, if the running program may read it.
+fn user_slice<'a>(address: usize, len: usize) -> Option<&'a [u8]> {
+    // a null pointer is never a valid slice, even though `0x0` is mapped in user programs
+    (address != 0 && process::fault_in_user_range(address, len, Access::Read))
+        // SAFETY: The range is mapped in the active user address space.
+        .then(|| unsafe { core::slice::from_raw_parts(address as *const u8, len) })
+}
+
+/// The user buffer at `address`, if the running program may write it.
+fn user_slice_mut<'a>(address: usize, len: usize) -> Option<&'a mut [u8]> {
+    (address != 0 && process::fault_in_user_range(address, len, Access::Write))
+        // SAFETY: The range is mapped writable in the active user address space.
+        .then(|| unsafe { core::slice::from_raw_parts_mut(address as *mut u8, len) })
+}
+
+/// The initramfs file `name`.
+fn initrd_file(name: &str) -> Option<CpioEntry<'static>> {
+    crate::initrd()?.files().find(|f| f.filename == name)
+}
+
+/// The NUL-terminated user string at `address`.
+fn user_c_str<'a>(address: usize) -> Option<&'a str> {
+    // look for the terminator page by page, so the string may end right before unmapped memory
+    let mut len = 0;
+    while len < MAX_USER_STRING_LEN {
+        let chunk_len = (PAGE_SIZE - (address + len) % PAGE_SIZE).min(MAX_USER_STRING_LEN - len);
+        let chunk = user_slice(address.checked_add(len)?, chunk_len)?;
+        if let Some(end) = chunk.iter().position(|&byte| byte == 0) {
+            let bytes = user_slice(address, len + end + 1)?;
+            return CStr::from_bytes_with_nul(bytes).ok()?.to_str().ok();
+        }
+        len += chunk_len;
+    }
+    None
+}
+
+/// `int getpid()`
+pub fn getpid(_e: &mut ExceptionContext) -> i64 {
+    process::current_pid().map_or(SYSCALL_FAILED, |pid| pid as i64)
+}
+
+/// `size_t uart_read(char buf[], size_t size)`
+///
+/// Blocks the thread of the program until input arrives, the core runs other threads meanwhile.
+/// Returns early if the program is killed, which terminates it before it sees the result.
+pub fn uart_read(e: &mut ExceptionContext) -> i64 {
+    let (buf, size) = (e.gpr[0] as usize, e.gpr[1] as usize);
+    let Some(buf) = user_slice_mut(buf, size) else {
+        return SYSCALL_FAILED;
+    };
+
+    for (read, byte) in buf.iter_mut().enumerate() {
+        match driver::console_read_char_interruptible(signal::kill_pending) {
+            Some(c) => *byte = c as u8,
+            None => return read as i64,
+        }
+    }
+
+    size as i64
+}
+
+/// `size_t uart_write(const char buf[], size_t size)`
+pub fn uart_write(e: &mut ExceptionContext) -> i64 {
+    let (buf, size) = (e.gpr[0] as usize, e.gpr[1] as usize);
+    let Some(buf) = user_slice(buf, size) else {
+        return SYSCALL_FAILED;
+    };
+
+    for &byte in buf {
+        console().write_char(byte as char);
+    }
+
+    size as i64
+}
+
+/// `int exec(const char *name, char *const argv[])`
+pub fn exec(e: &mut ExceptionContext) -> i64 {
+    let Some(name) = user_c_str(e.gpr[0] as usize) else {
+        return SYSCALL_FAILED;
+    };
+
+    let Some(program) = initrd_file(name) else {
+        return SYSCALL_FAILED;
+    };
+
+    match process::exec(e, &program) {
+        Ok(()) => 0,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `void exit(int status)`
+pub fn exit(e: &mut ExceptionContext) -> i64 {
+    process::exit(e.gpr[0] as i32)
+}
+
+/// `int fork()`
+pub fn fork(e: &mut ExceptionContext) -> i64 {
+    match process::fork(e) {
+        Ok(pid) => pid as i64,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `pid_t waitpid(pid_t pid, int *status)`, waits for any child if `pid` is -1.
+///
+/// The exit status is only stored if `status` is not null.
+pub fn waitpid(e: &mut ExceptionContext) -> i64 {
+    let (pid, status) = (e.gpr[0] as i64, e.gpr[1] as usize);
+    let pid = (pid != -1).then_some(pid as u64);
+    // checked before waiting, the status of the child would be lost afterwards
+    let status = match status {
+        0 => None,
+        address => match user_slice_mut(address, size_of::<i32>()) {
+            Some(status) => Some(status),
+            None => return SYSCALL_FAILED,
+        },
+    };
+
+    match process::wait(pid) {
+        Ok((pid, exit_status)) => {
+            if let Some(status) = status {
+                status.copy_from_slice(&exit_status.to_ne_bytes());
+            }
+            pid as i64
+        }
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `int mbox_call(unsigned char ch, unsigned int *mbox)`
+pub fn mbox_call(e: &mut ExceptionContext) -> i64 {
+    let (channel, mbox) = (e.gpr[0] as u8, e.gpr[1] as usize);
+    if mbox % 16 != 0 {
+        return 0;
+    }
+
+    // the first word of the message is its size, the VideoCore reads it from a kernel copy
+    let Some(size) = user_slice(mbox, 4).map(|size| u32::from_ne_bytes(size.try_into().unwrap()))
+    else {
+        return 0;
+    };
+    let size = size as usize;
+    if !(8..=MAX_MBOX_MESSAGE_SIZE).contains(&size) {
+        return 0;
+    }
+    let Some(user_message) = user_slice_mut(mbox, size) else {
+        return 0;
+    };
+
+    let mut message = Box::new(MboxMessage([0; MAX_MBOX_MESSAGE_SIZE]));
+    message.0[..size].copy_from_slice(user_message);
+
+    // SAFETY: The buffer is aligned, the message layout is the caller's responsibility.
+    let result = unsafe { driver::mailbox().call(channel, message.0.as_mut_ptr() as *mut u32) };
+    user_message.copy_from_slice(&message.0[..size]);
+
+    match result {
+        Ok(()) => 1,
+        Err(_) => 0,
+    }
+}
+
+/// `void *mmap(void *addr, size_t len, int prot, int flags, const char *file, size_t offset)`
+///
+/// There are no file descriptors, file mappings name an initramfs file instead. They cannot be
+/// writable. Only private mappings are supported.
+pub fn mmap(e: &mut ExceptionContext) -> i64 {
+    let [addr, len, prot, flags, file, offset] = e.gpr[..6].try_into().unwrap();
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0
+        || flags & !(MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS) != 0
+        || flags & MAP_PRIVATE == 0
+    {
+        return SYSCALL_FAILED;
+    }
+
+    let prot = PageFlags {
+        readable: prot != 0,
+        writable: prot & PROT_WRITE != 0,
+        executable: prot & PROT_EXEC != 0,
+    };
+    let backing = if flags & MAP_ANONYMOUS != 0 {
+        Backing::Anonymous
+    } else {
+        if prot.writable || offset as usize % PAGE_SIZE != 0 {
+            return SYSCALL_FAILED;
+        }
+        let Some(file) = user_c_str(file as usize).and_then(initrd_file) else {
+            return SYSCALL_FAILED;
+        };
+        Backing::File {
+            name: file.filename,
+            content: file.content,
+            offset: offset as usize,
+        }
+    };
+
+    let fixed = flags & MAP_FIXED != 0;
+    match process::mmap(addr as usize, len as usize, prot, backing, fixed) {
+        Ok(start) => start as i64,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `int munmap(void *addr, size_t len)`
+pub fn munmap(e: &mut ExceptionContext) -> i64 {
+    match process::munmap(e.gpr[0] as usize, e.gpr[1] as usize) {
+        Ok(()) => 0,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `sighandler_t signal(int signum, sighandler_t handler)`
+///
+/// `handler` is `SIG_DFL` (0), `SIG_IGN` (1) or the function to call, which returns the previous
+/// one.
+pub fn signal(e: &mut ExceptionContext) -> i64 {
+    let (signum, handler) = (e.gpr[0] as usize, e.gpr[1] as usize);
+    match signal::set_action(signum, SigAction::from_user(handler)) {
+        Ok(previous) => previous.to_user() as i64,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `int kill(pid_t pid, int sig)`
+pub fn kill(e: &mut ExceptionContext) -> i64 {
+    let (pid, signum) = (e.gpr[0], e.gpr[1] as usize);
+    match signal::kill(pid, signum) {
+        Ok(()) => 0,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
+
+/// `int sigreturn()`, only called by the trampoline signal handlers return to.
+pub fn sigreturn(e: &mut ExceptionContext) -> i64 {
+    if signal::sigreturn(e).is_err() {
+        process::exit(process::FAULT_EXIT_STATUS);
+    }
+
+    // the return value overwrites x0, keep the one of the interrupted program
+    e.gpr[0] as i64
+}
+
+/// `int sigprocmask(int how, sigset_t set)`, returns the previous mask.
+///
+/// The set is passed by value, a bit per signal.
+pub fn sigprocmask(e: &mut ExceptionContext) -> i64 {
+    let how = match e.gpr[0] {
+        SIG_BLOCK => MaskHow::Block,
+        SIG_UNBLOCK => MaskHow::Unblock,
+        SIG_SETMASK => MaskHow::SetMask,
+        _ => return SYSCALL_FAILED,
+    };
+    match signal::set_blocked(how, e.gpr[1]) {
+        Ok(previous) => previous as i64,
+        Err(_) => SYSCALL_FAILED,
+    }
+}
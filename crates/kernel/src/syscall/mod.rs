@@ -0,0 +1,46 @@
+//! System calls for user programs.
+//!
+//! A user program issues `svc 0` with the system call number in `x8` and the arguments in
+//! `x0` - `x5`. The return value is written back to `x0` before returning to the program.
+
+mod handlers;
+
+use crate::exception::ExceptionContext;
+
+type SyscallHandler = fn(&mut ExceptionContext) -> i64;
+
+/// System call table, indexed by the system call number.
+const SYSCALL_TABLE: &[SyscallHandler] = &[
+    handlers::getpid,      // 0
+    handlers::uart_read,   // 1
+    handlers::uart_write,  // 2
+    handlers::exec,        // 3
+    handlers::exit,        // 4
+    handlers::mbox_call,   // 5
+    handlers::fork,        // 6
+    handlers::waitpid,     // 7
+    handlers::mmap,        // 8
+    handlers::munmap,      // 9
+    handlers::signal,      // 10
+    handlers::kill,        // 11
+    handlers::sigreturn,   // 12
+    handlers::sigprocmask, // 13
+];
+
+/// Number of `sigreturn`, which the signal return trampoline calls.
+pub const SYS_SIGRETURN: usize = 12;
+
+/// Return value for failed system calls.
+const SYSCALL_FAILED: i64 = -1;
+
+/// Dispatch the system call requested by the trapped context.
+pub fn handle_syscall(e: &mut ExceptionContext) {
+    let number = e.gpr[8] as usize;
+
+    let ret = match SYSCALL_TABLE.get(number) {
+        Some(handler) => handler(e),
+        None => SYSCALL_FAILED,
+    };
+
+    e.gpr[0] = ret as u64;
+}
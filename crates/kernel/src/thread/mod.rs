@@ -0,0 +1,398 @@
+//! Kernel threads and their round-robin scheduler.
+//!
+//! Each thread runs on its own kernel stack. Switching threads saves the callee-saved registers
+//! of the current one in its `ThreadContext` and restores those of the next one in the run queue,
+//! see `switch.s`. The flow of control that calls `init` becomes the idle thread, which frees the
+//! threads that have exited whenever it runs, and waits for interrupts while no other thread is
+//! ready. Threads waiting for an event block on a `WaitQueue`, off the run queue.
+//!
+//! Threads are only scheduled on the core that called `init`. Besides yielding, the running thread
+//! is preempted once its time slice has expired, on the way out of the IRQ handler that interrupted
+//! it. That only happens at a safe point: in EL0, or in the kernel while preemption is enabled, see
+//! `small_std::sync::preempt`.
+
+use core::{
+    arch::{asm, global_asm},
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use device::cpu::core_id;
+use small_std::sync::{preempt, IRQSafeMutex};
+
+use crate::{
+    exception::{asynchronous, fp_simd},
+    memory::{self, mmu, PAGE_SIZE},
+    timer,
+};
+
+mod wait_queue;
+
+pub use wait_queue::WaitQueue;
+
+global_asm!(include_str!("switch.s"));
+
+/// Kernel stacks are `2^THREAD_STACK_ORDER` pages.
+const THREAD_STACK_ORDER: usize = 4;
+
+/// Time a thread runs before the next ready one is given the core.
+const TIME_SLICE: Duration = Duration::from_millis(10);
+
+/// Callee-saved registers of a thread that is not running.
+#[repr(C)]
+#[derive(Default)]
+struct ThreadContext {
+    /// x19 - x28, fp (x29) and lr (x30)
+    callee_saved: [u64; 12],
+    sp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    /// Waiting on a `WaitQueue`.
+    Blocked,
+    /// Exited, waiting for the idle thread to free it.
+    Zombie,
+}
+
+/// Pages of a kernel stack, freed when dropped.
+struct KernelStack {
+    /// Physical address of the pages.
+    pages: usize,
+}
+
+impl KernelStack {
+    fn new() -> Result<Self, &'static str> {
+        let pages = memory::alloc_pages(THREAD_STACK_ORDER).ok_or("out of memory")?;
+        Ok(Self { pages })
+    }
+
+    fn range(&self) -> Range<usize> {
+        let start = memory::phys_to_virt(self.pages);
+        start..start + (PAGE_SIZE << THREAD_STACK_ORDER)
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        memory::free_pages(self.pages).unwrap();
+    }
+}
+
+struct Thread {
+    tid: u64,
+    name: &'static str,
+    state: ThreadState,
+    context: ThreadContext,
+    stack_range: Range<usize>,
+    /// `None` for the idle thread, which runs on the stack it was started on.
+    stack: Option<KernelStack>,
+    /// Whether the thread owns the FPU, only the case for threads running a user program.
+    fp_simd_enabled: bool,
+    /// Physical address of the table translating the lower half, that of the user program the
+    /// thread runs if any.
+    user_table: usize,
+}
+
+impl Thread {
+    fn info(&self) -> ThreadInfo {
+        ThreadInfo {
+            tid: self.tid,
+            name: self.name,
+            state: self.state,
+        }
+    }
+}
+
+/// The threads are boxed, their context must stay in place while they are moved between queues.
+struct Scheduler {
+    current: Option<Box<Thread>>,
+    run_queue: VecDeque<Box<Thread>>,
+    #[allow(clippy::vec_box)]
+    blocked: Vec<Box<Thread>>,
+    #[allow(clippy::vec_box)]
+    zombies: Vec<Box<Thread>>,
+}
+
+impl Scheduler {
+    /// Move the blocked thread `tid` back to the run queue.
+    fn wake(&mut self, tid: u64) {
+        if let Some(index) = self.blocked.iter().position(|thread| thread.tid == tid) {
+            let mut thread = self.blocked.swap_remove(index);
+            thread.state = ThreadState::Ready;
+            self.run_queue.push_back(thread);
+            return;
+        }
+
+        // woken before it could switch away, see `WaitQueue::wait_until`
+        if let Some(current) = self.current.as_mut() {
+            if current.tid == tid && current.state == ThreadState::Blocked {
+                current.state = ThreadState::Running;
+            }
+        }
+    }
+}
+
+/// Information about a thread, see `threads`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    pub tid: u64,
+    pub name: &'static str,
+    pub state: ThreadState,
+}
+
+type ThreadEntry = Box<dyn FnOnce() + Send>;
+
+extern "C" {
+    fn __thread_switch_to(prev: *mut ThreadContext, next: *const ThreadContext);
+    fn __thread_start();
+}
+
+static NEXT_TID: AtomicU64 = AtomicU64::new(0);
+
+static SCHEDULER: IRQSafeMutex<Scheduler> = IRQSafeMutex::new(Scheduler {
+    current: None,
+    run_queue: VecDeque::new(),
+    blocked: Vec::new(),
+    zombies: Vec::new(),
+});
+
+/// The running thread, kept outside of the scheduler lock for backtraces and lookups from the
+/// exception handlers.
+static CURRENT_TID: AtomicU64 = AtomicU64::new(0);
+static CURRENT_STACK_START: AtomicUsize = AtomicUsize::new(0);
+static CURRENT_STACK_END: AtomicUsize = AtomicUsize::new(0);
+
+/// The core threads are scheduled on.
+static SCHEDULER_CORE: AtomicUsize = AtomicUsize::new(0);
+
+/// Set when the time slice of the running thread has expired.
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+/// Turn the executing flow of control, running on `stack_range`, into the idle thread.
+///
+/// # Safety
+///
+/// - Must only be called once
+pub unsafe fn init(stack_range: Range<usize>) {
+    let idle = Box::new(Thread {
+        tid: NEXT_TID.fetch_add(1, Ordering::Relaxed),
+        name: "idle",
+        state: ThreadState::Running,
+        context: ThreadContext::default(),
+        stack_range,
+        stack: None,
+        fp_simd_enabled: false,
+        user_table: mmu::user_table(),
+    });
+    set_current(&idle);
+    SCHEDULER_CORE.store(core_id(), Ordering::Relaxed);
+    SCHEDULER.lock().unwrap().current = Some(idle);
+
+    start_time_slice();
+}
+
+/// Start a thread running `f`, and return its TID. It exits once `f` returns.
+pub fn spawn<F>(name: &'static str, f: F) -> Result<u64, &'static str>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let stack = KernelStack::new()?;
+    let stack_range = stack.range();
+    // the entry is a fat pointer, box it again to pass it in a single register
+    let entry: Box<ThreadEntry> = Box::new(Box::new(f));
+
+    let mut context = ThreadContext::default();
+    context.callee_saved[0] = Box::into_raw(entry) as u64;
+    context.callee_saved[11] = __thread_start as usize as u64;
+    context.sp = stack_range.end as u64;
+
+    let tid = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+    let thread = Box::new(Thread {
+        tid,
+        name,
+        state: ThreadState::Ready,
+        context,
+        stack_range,
+        stack: Some(stack),
+        fp_simd_enabled: false,
+        user_table: mmu::identity_table(),
+    });
+    SCHEDULER.lock().unwrap().run_queue.push_back(thread);
+
+    Ok(tid)
+}
+
+/// Give the core to the next ready thread, if any.
+pub fn yield_now() {
+    schedule();
+}
+
+/// Switch to the next ready thread if the time slice of the running one has expired.
+///
+/// Called at the end of IRQ handling, with IRQs masked. The interrupted thread is left alone while
+/// preemption is disabled, it is preempted by a later interrupt once it has released its locks.
+pub fn preempt_on_irq_return() {
+    if core_id() != SCHEDULER_CORE.load(Ordering::Relaxed) || !preempt::is_enabled() {
+        return;
+    }
+
+    if NEED_RESCHED.load(Ordering::Relaxed) {
+        schedule();
+    }
+}
+
+/// Make the thread `tid` ready again if it is blocked, e.g. to let it notice a signal.
+///
+/// It checks the condition it waits for again, and blocks once more if it still does not hold.
+pub fn wake(tid: u64) {
+    SCHEDULER.lock().unwrap().wake(tid);
+}
+
+/// Terminate the running thread.
+pub fn exit() -> ! {
+    {
+        let mut scheduler = SCHEDULER.lock().unwrap();
+        let current = scheduler.current.as_mut().expect("threads not initialized");
+        assert!(current.stack.is_some(), "the idle thread cannot exit");
+        current.state = ThreadState::Zombie;
+    }
+    schedule();
+
+    unreachable!("zombie thread scheduled again")
+}
+
+/// Run the idle thread: free the exited threads, and wait for interrupts when no other thread is
+/// ready.
+pub fn idle() -> ! {
+    loop {
+        let zombies = core::mem::take(&mut SCHEDULER.lock().unwrap().zombies);
+        drop(zombies);
+
+        // an interrupt arriving between the check and `wfi` stays pending and ends the wait
+        let irq = asynchronous::irq_masked_scope();
+        if SCHEDULER.lock().unwrap().run_queue.is_empty() {
+            // SAFETY: Only waits for the next interrupt.
+            unsafe { asm!("wfi", options(nomem, nostack)) };
+            // let the interrupt be taken
+            drop(irq);
+        } else {
+            drop(irq);
+            yield_now();
+        }
+    }
+}
+
+/// The threads that have not been freed yet.
+pub fn threads() -> Vec<ThreadInfo> {
+    let scheduler = SCHEDULER.lock().unwrap();
+    let mut threads: Vec<_> = scheduler.current.iter().map(|t| t.info()).collect();
+    threads.extend(scheduler.run_queue.iter().map(|t| t.info()));
+    threads.extend(scheduler.blocked.iter().map(|t| t.info()));
+    threads.extend(scheduler.zombies.iter().map(|t| t.info()));
+    threads.sort_by_key(|thread| thread.tid);
+    threads
+}
+
+/// TID of the running thread, that of the idle thread before `init`.
+pub fn current_tid() -> u64 {
+    CURRENT_TID.load(Ordering::Relaxed)
+}
+
+/// Bounds of the stack of the running thread, `None` before `init`.
+pub fn current_stack_range() -> Option<Range<usize>> {
+    let range =
+        CURRENT_STACK_START.load(Ordering::Relaxed)..CURRENT_STACK_END.load(Ordering::Relaxed);
+    (!range.is_empty()).then_some(range)
+}
+
+fn set_current(thread: &Thread) {
+    CURRENT_TID.store(thread.tid, Ordering::Relaxed);
+    CURRENT_STACK_START.store(thread.stack_range.start, Ordering::Relaxed);
+    CURRENT_STACK_END.store(thread.stack_range.end, Ordering::Relaxed);
+}
+
+/// Flag the running thread for preemption every `TIME_SLICE`.
+fn start_time_slice() {
+    timer::set_timeout(TIME_SLICE, || {
+        NEED_RESCHED.store(true, Ordering::Relaxed);
+        start_time_slice();
+    });
+}
+
+/// Switch from the running thread to the next ready one, if any.
+///
+/// The running thread goes to the back of the run queue, unless it has blocked or exited. The next
+/// thread starts a new time slice.
+fn schedule() {
+    // the switch must not be interrupted, the masking state is restored per thread
+    let _irq = asynchronous::irq_masked_scope();
+    NEED_RESCHED.store(false, Ordering::Relaxed);
+
+    let (prev, next) = {
+        let mut scheduler = SCHEDULER.lock().unwrap();
+        let Some(mut next) = scheduler.run_queue.pop_front() else {
+            // keep running, a blocked thread checks its condition again
+            let current = scheduler.current.as_mut().unwrap();
+            if current.state == ThreadState::Blocked {
+                current.state = ThreadState::Running;
+            }
+            return;
+        };
+
+        next.state = ThreadState::Running;
+        set_current(&next);
+        let next_context = core::ptr::addr_of!(next.context);
+        let next_fp_simd_enabled = next.fp_simd_enabled;
+        let next_user_table = next.user_table;
+
+        let mut prev = scheduler.current.replace(next).unwrap();
+        prev.fp_simd_enabled = fp_simd::is_enabled();
+        let prev_user_table = mmu::user_table();
+        prev.user_table = prev_user_table;
+        let prev_context = core::ptr::addr_of_mut!(prev.context);
+        match prev.state {
+            ThreadState::Zombie => scheduler.zombies.push(prev),
+            ThreadState::Blocked => scheduler.blocked.push(prev),
+            ThreadState::Ready | ThreadState::Running => {
+                prev.state = ThreadState::Ready;
+                scheduler.run_queue.push_back(prev);
+            }
+        }
+
+        if next_fp_simd_enabled {
+            fp_simd::enable();
+        } else {
+            fp_simd::disable();
+        }
+
+        if next_user_table != prev_user_table {
+            // SAFETY: The table belongs to the user program of the next thread, which only frees
+            // it after resetting TTBR0 itself.
+            unsafe { mmu::set_user_table(next_user_table) };
+        }
+
+        (prev_context, next_context)
+    };
+
+    // SAFETY: Both threads are owned by the scheduler, and only freed once they are zombies that
+    // are not running.
+    unsafe { __thread_switch_to(prev, next) };
+}
+
+/// First Rust code of a thread, called by `__thread_start` with the entry from `spawn`.
+#[no_mangle]
+extern "C" fn thread_entry(entry: *mut ThreadEntry) -> ! {
+    // threads are switched to with IRQs masked, see `schedule`
+    asynchronous::local_irq_unmask();
+
+    // SAFETY: The entry has been leaked by `spawn` for this thread only.
+    let entry = unsafe { Box::from_raw(entry) };
+    entry();
+
+    exit()
+}
@@ -0,0 +1,46 @@
+// Offset into `ThreadContext`, see crates/kernel/src/thread/mod.rs
+.equ THREAD_CONTEXT_SP, 16 * 6
+
+// fn __thread_switch_to(prev: *mut ThreadContext, next: *const ThreadContext)
+//
+// Save the callee-saved registers and sp into `prev`, and resume the thread saved in `next`.
+// The call returns once another thread switches back to `prev`.
+__thread_switch_to:
+    stp     x19, x20, [x0, #16 * 0]
+    stp     x21, x22, [x0, #16 * 1]
+    stp     x23, x24, [x0, #16 * 2]
+    stp     x25, x26, [x0, #16 * 3]
+    stp     x27, x28, [x0, #16 * 4]
+    stp     x29, lr,  [x0, #16 * 5]
+    mov     x9,  sp
+    str     x9,  [x0, #THREAD_CONTEXT_SP]
+
+    ldp     x19, x20, [x1, #16 * 0]
+    ldp     x21, x22, [x1, #16 * 1]
+    ldp     x23, x24, [x1, #16 * 2]
+    ldp     x25, x26, [x1, #16 * 3]
+    ldp     x27, x28, [x1, #16 * 4]
+    ldp     x29, lr,  [x1, #16 * 5]
+    ldr     x9,  [x1, #THREAD_CONTEXT_SP]
+    mov     sp,  x9
+
+    ret
+
+.size   __thread_switch_to, . - __thread_switch_to
+.type   __thread_switch_to, function
+.global __thread_switch_to
+
+// First instructions of a thread, "returned" to by `__thread_switch_to` with the entry prepared
+// by `spawn` in x19.
+__thread_start:
+    mov     x0,  x19
+    // no frame to return to, terminate backtraces here
+    mov     x29, xzr
+    mov     lr,  xzr
+    b       thread_entry
+
+.size   __thread_start, . - __thread_start
+.type   __thread_start, function
+.global __thread_start
+
+// vim: ft=asm
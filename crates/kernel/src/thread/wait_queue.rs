@@ -0,0 +1,56 @@
+use alloc::vec::Vec;
+use small_std::sync::IRQSafeMutex;
+
+use super::{schedule, ThreadState, SCHEDULER};
+
+/// Threads blocked until an event, e.g. input arriving, wakes them.
+///
+/// Blocked threads stay off the run queue, so the core can idle while nothing is ready.
+pub struct WaitQueue {
+    /// TIDs of the waiting threads.
+    waiters: IRQSafeMutex<Vec<u64>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: IRQSafeMutex::new(Vec::new()),
+        }
+    }
+
+    /// Block the running thread until `condition` holds, checking it again on every wake up.
+    ///
+    /// The condition is checked with the queue locked, so a wake up racing with it is not lost.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            {
+                let mut waiters = self.waiters.lock().unwrap();
+                if condition() {
+                    return;
+                }
+
+                let mut scheduler = SCHEDULER.lock().unwrap();
+                let current = scheduler.current.as_mut().expect("threads not initialized");
+                assert!(current.stack.is_some(), "the idle thread cannot block");
+                if !waiters.contains(&current.tid) {
+                    waiters.push(current.tid);
+                }
+                current.state = ThreadState::Blocked;
+            }
+            schedule();
+        }
+    }
+
+    /// Make all the waiting threads ready again. Safe to call from IRQ handlers.
+    pub fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.waiters.lock().unwrap());
+        if waiters.is_empty() {
+            return;
+        }
+
+        let mut scheduler = SCHEDULER.lock().unwrap();
+        for tid in waiters {
+            scheduler.wake(tid);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Software timers multiplexed on the core timer.
+//!
+//! Timers are kept in a queue sorted by their deadline, and the core timer alarm is always set to
+//! the nearest one.
+
+use core::time::Duration;
+
+use alloc::{boxed::Box, vec::Vec};
+use small_std::sync::Mutex;
+
+use crate::{
+    driver,
+    exception::{
+        asynchronous::exec_with_irq_masked,
+        task_queue::{self, TaskPriority},
+    },
+};
+
+/// Priority of the deferred task running the expired timers.
+const TIMER_TASK_PRIORITY: TaskPriority = 1;
+
+type TimerCallback = Box<dyn FnOnce() + Send>;
+
+struct Timer {
+    deadline: Duration,
+    callback: TimerCallback,
+}
+
+struct TimerQueue {
+    timers: Vec<Timer>,
+}
+
+static TIMER_QUEUE: Mutex<TimerQueue> = Mutex::new(TimerQueue::new());
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self { timers: Vec::new() }
+    }
+
+    fn insert(&mut self, timer: Timer) {
+        // keep insertion order for timers with the same deadline
+        let index = self
+            .timers
+            .partition_point(|t| t.deadline <= timer.deadline);
+        self.timers.insert(index, timer);
+    }
+
+    fn pop_expired(&mut self, now: Duration) -> Option<Timer> {
+        match self.timers.first() {
+            Some(timer) if timer.deadline <= now => Some(self.timers.remove(0)),
+            _ => None,
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.timers.first().map(|timer| timer.deadline)
+    }
+}
+
+/// Call `callback` once `timeout` has elapsed.
+///
+/// The callback runs as a deferred interrupt task, with IRQs unmasked.
+pub fn set_timeout<F>(timeout: Duration, callback: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let core_timer = driver::core_timer();
+    let timer = Timer {
+        deadline: core_timer.uptime() + timeout,
+        callback: Box::new(callback),
+    };
+
+    exec_with_irq_masked(|| {
+        let mut queue = TIMER_QUEUE.lock().unwrap();
+        queue.insert(timer);
+        core_timer.set_alarm(queue.next_deadline());
+    });
+}
+
+/// Top half of the core timer IRQ, defers the expired timers to the task queue.
+pub fn handle_timer_irq() {
+    task_queue::enqueue(TIMER_TASK_PRIORITY, handle_expired_timers);
+}
+
+/// Run the callbacks of all expired timers and re-arm the alarm for the nearest remaining one.
+fn handle_expired_timers() {
+    let core_timer = driver::core_timer();
+
+    loop {
+        let expired =
+            exec_with_irq_masked(|| TIMER_QUEUE.lock().unwrap().pop_expired(core_timer.uptime()));
+        let Some(timer) = expired else {
+            break;
+        };
+        (timer.callback)();
+    }
+
+    exec_with_irq_masked(|| {
+        let queue = TIMER_QUEUE.lock().unwrap();
+        core_timer.set_alarm(queue.next_deadline());
+    });
+}
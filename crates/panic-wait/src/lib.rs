@@ -5,7 +5,7 @@
 
 mod utils;
 use core::panic::PanicInfo;
-use small_std::println;
+use small_std::{backtrace::Backtrace, fmt::print::console::console};
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -16,15 +16,19 @@ fn panic(info: &PanicInfo) -> ! {
         None => ("<unknown>", 0, 0),
     };
 
-    println!(
+    // The regular write path might wait for a lock held by the panicking code, or for interrupts
+    // that never come back to drain a buffered console
+    let _ = console().panic_write_fmt(format_args!(
         "Kernel panicked!\n\n\
         Panic location: {}:{}:{}\n\n\
-        {}",
+        {}\n\n\
+        {}\n",
         location,
         line,
         column,
-        info.message().unwrap()
-    );
+        info.message().unwrap(),
+        Backtrace::capture()
+    ));
 
     utils::wait_forever();
 }
@@ -0,0 +1,136 @@
+//! Stack backtraces following the AArch64 frame records.
+//!
+//! Every function built with frame pointers pushes a frame record `[previous x29, x30]` and
+//! points x29 at it, so the records form a linked list from the innermost frame to the outermost.
+//! The walk only follows records inside the stack bounds registered by the kernel.
+
+use core::{arch::asm, ops::Range};
+
+use crate::sync::IRQSafeMutex;
+
+/// Maximum number of frames printed, in case the frame records are corrupted into a loop.
+const MAX_FRAMES: usize = 32;
+
+/// Returns the bounds of the stack the executing code runs on.
+pub type StackBoundsFn = fn() -> Range<usize>;
+
+/// Resolves an address to the name of the enclosing function and the offset into it.
+pub type SymbolizeFn = fn(usize) -> Option<(&'static str, usize)>;
+
+static STACK_BOUNDS: IRQSafeMutex<Option<StackBoundsFn>> = IRQSafeMutex::new(None);
+static SYMBOLIZER: IRQSafeMutex<Option<SymbolizeFn>> = IRQSafeMutex::new(None);
+
+pub fn register_stack_bounds(stack_bounds: StackBoundsFn) {
+    let mut current = STACK_BOUNDS.lock().unwrap();
+    *current = Some(stack_bounds);
+}
+
+pub fn register_symbolizer(symbolizer: SymbolizeFn) {
+    let mut current = SYMBOLIZER.lock().unwrap();
+    *current = Some(symbolizer);
+}
+
+fn write_frame(
+    f: &mut core::fmt::Formatter<'_>,
+    index: usize,
+    address: usize,
+) -> core::fmt::Result {
+    write!(f, "\n      #{:<2}: {:#016x}", index, address)?;
+
+    let symbolizer = *SYMBOLIZER.lock().unwrap();
+    if let Some((name, offset)) = symbolizer.and_then(|symbolize| symbolize(address)) {
+        write!(f, " - {}+{:#x}", name, offset)?;
+    }
+
+    Ok(())
+}
+
+/// A call chain starting at a frame record, printed as a list of return addresses.
+pub struct Backtrace {
+    /// Address the innermost frame was executing, if known.
+    pc: Option<usize>,
+    /// Address of the innermost frame record.
+    fp: usize,
+}
+
+#[repr(C)]
+struct FrameRecord {
+    fp: usize,
+    lr: usize,
+}
+
+impl Backtrace {
+    /// Backtrace of the caller.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let fp: usize;
+        unsafe { asm!("mov {}, x29", out(reg) fp, options(nomem, nostack)) };
+        Self { pc: None, fp }
+    }
+
+    /// Backtrace of a trapped context, e.g. from the registers saved on exception entry.
+    pub fn from_frame(pc: usize, fp: usize) -> Self {
+        Self { pc: Some(pc), fp }
+    }
+
+    /// Iterate the return addresses of the frame records, innermost first.
+    ///
+    /// Nothing is returned until the stack bounds are registered.
+    pub fn frames(&self) -> impl Iterator<Item = usize> {
+        let stack_bounds = *STACK_BOUNDS.lock().unwrap();
+        self.return_addresses(stack_bounds.map_or(0..0, |stack_bounds| stack_bounds()))
+    }
+
+    /// Iterate the return addresses of the frame records within `bounds`.
+    fn return_addresses(&self, bounds: Range<usize>) -> impl Iterator<Item = usize> {
+        let mut next_fp = Some(self.fp);
+        core::iter::from_fn(move || {
+            let fp = next_fp.take()?;
+            let record_end = fp.checked_add(core::mem::size_of::<FrameRecord>())?;
+            if fp % core::mem::align_of::<FrameRecord>() != 0
+                || !bounds.contains(&fp)
+                || record_end > bounds.end
+            {
+                return None;
+            }
+
+            let record = unsafe { &*(fp as *const FrameRecord) };
+            if record.lr == 0 {
+                return None;
+            }
+            // the stack grows downwards, so the callers' records must be above
+            if record.fp > fp {
+                next_fp = Some(record.fp);
+            }
+            Some(record.lr)
+        })
+        .take(MAX_FRAMES)
+    }
+}
+
+impl core::fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Backtrace:")?;
+
+        let mut frames = 0;
+        if let Some(pc) = self.pc {
+            write_frame(f, frames, pc)?;
+            frames += 1;
+        }
+
+        let Some(stack_bounds) = *STACK_BOUNDS.lock().unwrap() else {
+            return write!(f, "\n      <no stack bounds registered>");
+        };
+
+        for lr in self.return_addresses(stack_bounds()) {
+            write_frame(f, frames, lr)?;
+            frames += 1;
+        }
+
+        if frames == 0 {
+            write!(f, "\n      <empty>")?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 //! System console.
 
-use crate::sync::Mutex;
+use crate::sync::IRQSafeMutex;
 mod null_console;
 
 /// Console write functions.
@@ -20,6 +20,12 @@ pub trait Write {
 
     /// Block until all characters have been physically put on the output.
     fn flush(&self);
+
+    /// Write a Rust format string from the panic handler, straight to the device.
+    ///
+    /// Must neither wait for a lock nor rely on interrupts, the panicking code might hold the
+    /// lock or have them masked.
+    fn panic_write_fmt(&self, args: core::fmt::Arguments) -> core::fmt::Result;
 }
 
 /// Console read functions.
@@ -29,6 +35,11 @@ pub trait Read {
         ' '
     }
 
+    /// Read a single character if one is available, without blocking.
+    fn try_read_char(&self) -> Option<char> {
+        None
+    }
+
     /// Clear RX buffers, if any.
     fn clear_rx(&self);
 }
@@ -38,7 +49,7 @@ pub trait All: Write + Read {}
 
 type Console = &'static (dyn All + Sync);
 
-static CURRENT_CONSOLE: Mutex<Console> = Mutex::new(&null_console::NULL_CONSOLE);
+static CURRENT_CONSOLE: IRQSafeMutex<Console> = IRQSafeMutex::new(&null_console::NULL_CONSOLE);
 
 pub fn register_console(new_console: Console) {
     let mut current_console = CURRENT_CONSOLE.lock().unwrap();
@@ -10,6 +10,10 @@ impl super::Write for NullConsole {
     }
 
     fn flush(&self) {}
+
+    fn panic_write_fmt(&self, _args: core::fmt::Arguments) -> core::fmt::Result {
+        core::fmt::Result::Ok(())
+    }
 }
 
 impl super::Read for NullConsole {
@@ -2,6 +2,7 @@
 
 #![no_std]
 
+pub mod backtrace;
 pub mod fmt;
 pub mod sync;
 
@@ -0,0 +1,114 @@
+use core::{
+    arch::asm,
+    ops::{Deref, DerefMut},
+};
+
+use super::{Mutex, MutexGuard};
+
+/// A spinlock that masks IRQs on the executing core while it is held.
+///
+/// Safe to share between IRQ handlers and the rest of the kernel.
+pub struct IRQSafeMutex<T>
+where
+    T: ?Sized,
+{
+    inner: Mutex<T>,
+}
+
+impl<T> IRQSafeMutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            inner: Mutex::new(data),
+        }
+    }
+}
+
+impl<T> IRQSafeMutex<T>
+where
+    T: ?Sized,
+{
+    pub fn lock(&self) -> Result<IRQSafeMutexGuard<'_, T>, &'static str> {
+        // mask before spinning, so an IRQ handler cannot interrupt the owner on this core
+        let daif = mask_irqs();
+        let guard = self.inner.lock()?;
+
+        Ok(IRQSafeMutexGuard {
+            guard: Some(guard),
+            daif,
+        })
+    }
+
+    /// Take the lock only if it is free.
+    pub fn try_lock(&self) -> Option<IRQSafeMutexGuard<'_, T>> {
+        let daif = mask_irqs();
+        match self.inner.try_lock() {
+            Some(guard) => Some(IRQSafeMutexGuard {
+                guard: Some(guard),
+                daif,
+            }),
+            None => {
+                restore_irqs(daif);
+                None
+            }
+        }
+    }
+}
+
+pub struct IRQSafeMutexGuard<'a, T>
+where
+    T: 'a + ?Sized,
+{
+    /// Always `Some` until dropped, so the lock is released before IRQs are restored.
+    guard: Option<MutexGuard<'a, T>>,
+    daif: u64,
+}
+
+impl<T> Deref for IRQSafeMutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for IRQSafeMutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for IRQSafeMutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.guard.take();
+        restore_irqs(self.daif);
+    }
+}
+
+/// Mask IRQs on the executing core, returning the previous DAIF value.
+#[inline(always)]
+fn mask_irqs() -> u64 {
+    let daif: u64;
+    unsafe {
+        asm!(
+            "mrs {daif}, DAIF",
+            "msr DAIFSet, #0b0010",
+            daif = out(reg) daif,
+            options(nostack),
+        )
+    };
+    daif
+}
+
+#[inline(always)]
+fn restore_irqs(daif: u64) {
+    unsafe { asm!("msr DAIF, {daif}", daif = in(reg) daif, options(nostack)) };
+}
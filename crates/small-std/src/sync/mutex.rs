@@ -0,0 +1,106 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::preempt;
+
+/// A spinlock.
+///
+/// Must not be shared with IRQ handlers, as an IRQ arriving while the lock is held on the same
+/// core would spin forever. Use `IRQSafeMutex` for data touched in interrupt context.
+///
+/// Preemption is disabled on the owning core while the lock is held, see `preempt`.
+pub struct Mutex<T>
+where
+    T: ?Sized,
+{
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for Mutex<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for Mutex<T> where T: ?Sized + Send {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> Mutex<T>
+where
+    T: ?Sized,
+{
+    pub fn lock(&self) -> Result<MutexGuard<'_, T>, &'static str> {
+        preempt::disable();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // spin on a plain load to keep the cache line shared while waiting
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+
+        Ok(MutexGuard { lock: self })
+    }
+
+    /// Take the lock only if it is free.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        preempt::disable();
+        match self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Some(MutexGuard { lock: self }),
+            Err(_) => {
+                preempt::enable();
+                None
+            }
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T>
+where
+    T: 'a + ?Sized,
+{
+    lock: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        preempt::enable();
+    }
+}
@@ -0,0 +1,68 @@
+//! Per-core preemption count.
+//!
+//! The scheduler only switches away from a thread interrupted in the kernel while the count of its
+//! core is 0. Lock guards raise the count while they are held, so a thread is never preempted
+//! with a lock that the next thread might spin on.
+
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Cores of the Raspberry Pi 3.
+const MAX_CORES: usize = 4;
+
+static PREEMPT_COUNTS: [AtomicUsize; MAX_CORES] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Count of the executing core.
+///
+/// The count is only changed by the core it belongs to, which cannot be left in between.
+fn preempt_count() -> &'static AtomicUsize {
+    let mpidr: u64;
+    // SAFETY: Only reads the ID of the executing core.
+    unsafe { asm!("mrs {}, MPIDR_EL1", out(reg) mpidr, options(nomem, nostack)) };
+    &PREEMPT_COUNTS[mpidr as usize % MAX_CORES]
+}
+
+/// Forbid preemption on the executing core until the matching `enable`.
+#[inline(always)]
+pub fn disable() {
+    preempt_count().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Undo a `disable`, preemption is allowed again once every `disable` has been undone.
+#[inline(always)]
+pub fn enable() {
+    let previous = preempt_count().fetch_sub(1, Ordering::Relaxed);
+    debug_assert!(previous > 0, "unbalanced preempt::enable");
+}
+
+/// Whether the executing core may switch threads at an interrupt.
+pub fn is_enabled() -> bool {
+    preempt_count().load(Ordering::Relaxed) == 0
+}
+
+/// Preemption disabled, enabled again when dropped.
+#[must_use = "preemption is enabled again as soon as the guard is dropped"]
+pub struct PreemptGuard {
+    _private: (),
+}
+
+impl Drop for PreemptGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        enable();
+    }
+}
+
+/// Disable preemption until the returned guard is dropped.
+#[inline(always)]
+pub fn disabled_scope() -> PreemptGuard {
+    disable();
+    PreemptGuard { _private: () }
+}
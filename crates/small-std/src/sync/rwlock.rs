@@ -0,0 +1,140 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::preempt;
+
+/// Set in the state while a writer holds the lock, the other bits count the readers.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A spinning reader-writer lock.
+///
+/// Like `Mutex`, it must not be shared with IRQ handlers, and disables preemption while held.
+pub struct RwLock<T>
+where
+    T: ?Sized,
+{
+    state: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for RwLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for RwLock<T> where T: ?Sized + Send + Sync {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> RwLock<T>
+where
+    T: ?Sized,
+{
+    /// Lock for shared read access, waiting for a writer to leave.
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, T>, &'static str> {
+        preempt::disable();
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 {
+                core::hint::spin_loop();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            }
+
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(RwLockReadGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    /// Lock for exclusive write access, waiting for all readers and writers to leave.
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, T>, &'static str> {
+        preempt::disable();
+        while self
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+
+        Ok(RwLockWriteGuard { lock: self })
+    }
+}
+
+pub struct RwLockReadGuard<'a, T>
+where
+    T: 'a + ?Sized,
+{
+    lock: &'a RwLock<T>,
+}
+
+pub struct RwLockWriteGuard<'a, T>
+where
+    T: 'a + ?Sized,
+{
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+        preempt::enable();
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        preempt::enable();
+    }
+}
@@ -18,10 +18,10 @@ pub fn register_drivers() -> Result<(), &'static str> {
 
     let driver_manager = device::driver::driver_manager();
 
-    let gpio = DeviceDriverDescriptor::new(&GPIO, Some(gpio_post_init));
+    let gpio = DeviceDriverDescriptor::new(&GPIO, Some(gpio_post_init), None);
     driver_manager.register_driver(gpio);
 
-    let mini_uart = DeviceDriverDescriptor::new(&MINI_UART, Some(mini_uart_post_init));
+    let mini_uart = DeviceDriverDescriptor::new(&MINI_UART, Some(mini_uart_post_init), None);
     driver_manager.register_driver(mini_uart);
 
     INIT_DONE.store(true, Ordering::Relaxed);
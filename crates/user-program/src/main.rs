@@ -2,65 +2,107 @@
 #![no_std]
 #![no_main]
 
+mod syscall;
+
 use core::{
-    arch::asm,
     fmt::Write,
-    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use panic_wait as _;
 
-const MMIO_BASE: usize = 0x3F00_0000;
-const AUX_MU_IO: *mut u32 = (MMIO_BASE + 0x0021_5040) as _;
-const AUX_MU_LSR: *const u32 = (MMIO_BASE + 0x0021_5054) as _;
-
 #[allow(clippy::missing_safety_doc)]
 #[no_mangle]
 pub extern "C" fn main() -> ! {
     println!("Hello from user program!");
+    println!("PID: {}", syscall::getpid());
+
+    match board_revision() {
+        Some(revision) => println!("Board revision: {:#x}", revision),
+        None => println!("Failed to get board revision"),
+    }
+
+    let flags = syscall::MAP_PRIVATE | syscall::MAP_ANONYMOUS;
+    let prot = syscall::PROT_READ | syscall::PROT_WRITE;
+    let page = syscall::mmap(core::ptr::null_mut(), 4096, prot, flags, None, 0);
+    if page.is_null() {
+        println!("Failed to map a page");
+    } else {
+        unsafe { page.write_volatile(42) };
+        println!("Mapped a page at {:p}", page);
+        syscall::munmap(page, 4096);
+    }
+
+    syscall::signal(syscall::SIGINT, on_sigint);
+    syscall::kill(syscall::getpid(), syscall::SIGINT);
+    println!("Returned from the SIGINT handler");
 
-    unsafe {
-        asm!(
-            "    mov x0, 0",
-            "1:",
-            "    add x0, x0, 1",
-            "    svc 0",
-            "    cmp x0, 5",
-            "    blt 1b",
-            "1:",
-            "    b   1b",
-        );
-    };
-
-    unreachable!();
+    // A child blocked in the kernel must still die on SIGKILL and be reaped with 128 + 9.
+    let parent = syscall::getpid();
+    SIGINT_CAUGHT.store(false, Ordering::SeqCst);
+    match syscall::fork() {
+        0 => {
+            println!("Child PID: {}", syscall::getpid());
+            syscall::kill(parent, syscall::SIGINT);
+            let mut byte = [0];
+            syscall::uart_read(&mut byte);
+            println!("Child survived SIGKILL");
+            syscall::exit(0);
+        }
+        pid if pid > 0 => {
+            println!("Forked child {}", pid);
+            while !SIGINT_CAUGHT.load(Ordering::SeqCst) {
+                core::hint::spin_loop();
+            }
+            syscall::kill(pid, syscall::SIGKILL);
+            let mut status = 0;
+            syscall::waitpid(pid, &mut status);
+            if status == 128 + syscall::SIGKILL as i32 {
+                println!("Killed blocked child {}: ok", pid);
+            } else {
+                println!("Killed blocked child {}: FAILED, status {}", pid, status);
+            }
+        }
+        _ => println!("Failed to fork"),
+    }
+
+    syscall::exit(0);
 }
 
-struct MiniUart;
+static SIGINT_CAUGHT: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(signal: u64) {
+    println!("Caught signal {}", signal);
+    SIGINT_CAUGHT.store(true, Ordering::SeqCst);
+}
+
+fn board_revision() -> Option<u32> {
+    const GET_BOARD_REVISION: u32 = 0x0001_0002;
+
+    #[repr(C, align(16))]
+    struct Message([u32; 7]);
 
-impl MiniUart {
-    fn write_byte(&self, b: u8) {
-        while (unsafe { read_volatile(AUX_MU_LSR) } & (1 << 5)) == 0 {}
-        unsafe { write_volatile(AUX_MU_IO, b as u32) };
+    let mut message = Message([7 * 4, 0, GET_BOARD_REVISION, 4, 0, 0, 0]);
+    if unsafe { syscall::mbox_call(8, message.0.as_mut_ptr()) } {
+        Some(message.0[5])
+    } else {
+        None
     }
 }
 
-impl core::fmt::Write for MiniUart {
+struct Uart;
+
+impl core::fmt::Write for Uart {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for b in s.bytes() {
-            self.write_byte(b);
-        }
+        syscall::uart_write(s.as_bytes());
         Ok(())
     }
 }
 
 fn _print(args: core::fmt::Arguments) {
-    unsafe {
-        MINI_UART.write_fmt(args).unwrap();
-    }
+    Uart.write_fmt(args).unwrap();
 }
 
-static mut MINI_UART: MiniUart = MiniUart;
-
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => (_print(format_args!($($arg)*)));
@@ -0,0 +1,141 @@
+//! Thin wrappers around the kernel system calls.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+const SYS_GETPID: u64 = 0;
+const SYS_UART_READ: u64 = 1;
+const SYS_UART_WRITE: u64 = 2;
+const SYS_EXEC: u64 = 3;
+const SYS_EXIT: u64 = 4;
+const SYS_MBOX_CALL: u64 = 5;
+const SYS_FORK: u64 = 6;
+const SYS_WAITPID: u64 = 7;
+const SYS_MMAP: u64 = 8;
+const SYS_MUNMAP: u64 = 9;
+const SYS_SIGNAL: u64 = 10;
+const SYS_KILL: u64 = 11;
+const SYS_SIGPROCMASK: u64 = 13;
+
+pub const PROT_READ: u64 = 0x1;
+pub const PROT_WRITE: u64 = 0x2;
+pub const PROT_EXEC: u64 = 0x4;
+pub const MAP_PRIVATE: u64 = 0x02;
+pub const MAP_FIXED: u64 = 0x10;
+pub const MAP_ANONYMOUS: u64 = 0x20;
+
+pub const SIGINT: u64 = 2;
+pub const SIGKILL: u64 = 9;
+pub const SIGTERM: u64 = 15;
+
+pub const SIG_BLOCK: u64 = 0;
+pub const SIG_UNBLOCK: u64 = 1;
+pub const SIG_SETMASK: u64 = 2;
+
+#[inline(always)]
+unsafe fn syscall(number: u64, arg0: u64, arg1: u64) -> i64 {
+    let ret: i64;
+    asm!(
+        "svc 0",
+        inlateout("x0") arg0 => ret,
+        in("x1") arg1,
+        in("x8") number,
+    );
+    ret
+}
+
+#[inline(always)]
+unsafe fn syscall6(number: u64, args: [u64; 6]) -> i64 {
+    let ret: i64;
+    asm!(
+        "svc 0",
+        inlateout("x0") args[0] => ret,
+        in("x1") args[1],
+        in("x2") args[2],
+        in("x3") args[3],
+        in("x4") args[4],
+        in("x5") args[5],
+        in("x8") number,
+    );
+    ret
+}
+
+pub fn getpid() -> i64 {
+    unsafe { syscall(SYS_GETPID, 0, 0) }
+}
+
+pub fn uart_read(buf: &mut [u8]) -> usize {
+    unsafe { syscall(SYS_UART_READ, buf.as_mut_ptr() as u64, buf.len() as u64) as usize }
+}
+
+pub fn uart_write(buf: &[u8]) -> usize {
+    unsafe { syscall(SYS_UART_WRITE, buf.as_ptr() as u64, buf.len() as u64) as usize }
+}
+
+/// Replace the current program with `name`, which must be null-terminated.
+pub fn exec(name: &core::ffi::CStr) -> i64 {
+    unsafe { syscall(SYS_EXEC, name.as_ptr() as u64, 0) }
+}
+
+pub fn exit(status: i32) -> ! {
+    unsafe { syscall(SYS_EXIT, status as u64, 0) };
+    unreachable!()
+}
+
+/// # Safety
+///
+/// - `mbox` must point to a 16-byte aligned mailbox message buffer.
+pub unsafe fn mbox_call(channel: u8, mbox: *mut u32) -> bool {
+    syscall(SYS_MBOX_CALL, channel as u64, mbox as u64) != 0
+}
+
+/// Returns 0 in the child, and the PID of the child in the parent, which runs concurrently.
+pub fn fork() -> i64 {
+    unsafe { syscall(SYS_FORK, 0, 0) }
+}
+
+/// Wait for the child `pid` to exit, or for any child if `pid` is -1, and store its exit status.
+/// Returns the PID of the child, or -1 if there is no such child.
+pub fn waitpid(pid: i64, status: &mut i32) -> i64 {
+    unsafe { syscall(SYS_WAITPID, pid as u64, status as *mut i32 as u64) }
+}
+
+/// Map `len` bytes, of the initramfs file `file` from `offset` on unless `MAP_ANONYMOUS` is set.
+///
+/// Returns a null pointer on failure.
+pub fn mmap(
+    addr: *mut u8,
+    len: usize,
+    prot: u64,
+    flags: u64,
+    file: Option<&core::ffi::CStr>,
+    offset: usize,
+) -> *mut u8 {
+    let file = file.map_or(0, |file| file.as_ptr() as u64);
+    let args = [addr as u64, len as u64, prot, flags, file, offset as u64];
+    match unsafe { syscall6(SYS_MMAP, args) } {
+        -1 => core::ptr::null_mut(),
+        addr => addr as *mut u8,
+    }
+}
+
+pub fn munmap(addr: *mut u8, len: usize) -> i64 {
+    unsafe { syscall(SYS_MUNMAP, addr as u64, len as u64) }
+}
+
+/// Call `handler` with the signal number when `signal` is delivered, returns -1 on failure.
+///
+/// The handler returns through a trampoline the kernel maps in every program.
+pub fn signal(signal: u64, handler: extern "C" fn(u64)) -> i64 {
+    unsafe { syscall(SYS_SIGNAL, signal, handler as usize as u64) }
+}
+
+pub fn kill(pid: i64, signal: u64) -> i64 {
+    unsafe { syscall(SYS_KILL, pid as u64, signal) }
+}
+
+/// Change the blocked signals, a bit per signal, and return the previous mask.
+pub fn sigprocmask(how: u64, set: u64) -> i64 {
+    unsafe { syscall(SYS_SIGPROCMASK, how, set) }
+}
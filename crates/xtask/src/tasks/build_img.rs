@@ -1,6 +1,6 @@
 use std::{collections::HashMap, ffi::OsStr, path::PathBuf, process::Command};
 
-use super::{BinTarget, Error, Result, TARGET_TRIPLE};
+use super::{symbols, BinTarget, Error, Result, TARGET_TRIPLE};
 use crate::envs;
 
 #[derive(Debug, clap::Args)]
@@ -25,7 +25,7 @@ where
     Cargo: Fn(&str, Option<HashMap<String, String>>) -> Result<()>,
 {
     let rust_flags = format!(
-        "-C target-cpu=cortex-a53 -C link-arg=--library-path={0}/crates/{1} -C link-arg=--script={1}.ld -D warnings",
+        "-C target-cpu=cortex-a53 -C force-frame-pointers=yes -C link-arg=--library-path={0}/crates/{1} -C link-arg=--script={1}.ld -D warnings",
         project_root.display(),
         args.target.as_str(),
     );
@@ -49,8 +49,9 @@ where
     )?;
 
     let release_dir = project_root.join(format!("target/{}/{}", TARGET_TRIPLE, profile));
-    let elf = release_dir.join(args.target.as_str());
-    let output_img = release_dir.join(args.target.image_name());
+    let target = args.target;
+    let elf = release_dir.join(target.as_str());
+    let output_img = release_dir.join(target.image_name());
 
     let mut command = Command::new("rust-objcopy");
     command
@@ -71,6 +72,11 @@ where
         return Err(Error::CommandFailed("rust-objcopy"));
     }
 
+    if target == BinTarget::Kernel {
+        let num_symbols = symbols::append_symbol_table(&elf, &output_img)?;
+        tracing::info!(symbols = num_symbols, "Symbol table embedded");
+    }
+
     let image_size = output_img.metadata()?.len();
     tracing::info!(
         image = %output_img.display(),
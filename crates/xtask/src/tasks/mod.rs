@@ -2,6 +2,7 @@ pub mod build_img;
 mod macros;
 pub mod push_kernel;
 pub mod qemu;
+mod symbols;
 
 use std::{
     collections::HashMap,
@@ -23,6 +24,9 @@ pub enum Error {
     #[error("push kernel failed: {0}")]
     PushKernelFailed(#[from] push_kernel::Error),
 
+    #[error("failed to embed symbol table: {0}")]
+    SymbolTableFailed(#[from] symbols::Error),
+
     #[error("could not determine repository root")]
     CouldNotDetermineRepositoryRoot,
     #[error("failed to run command '{0}'")]
@@ -0,0 +1,220 @@
+use std::{fs, path::Path};
+
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+
+/// Start of the region reserved for the symbol table by the kernel linker script.
+const SYMBOLS_START: &str = "__kernel_symbols_start";
+/// End of the region reserved for the symbol table by the kernel linker script.
+const SYMBOLS_END: &str = "__kernel_symbols_end_exclusive";
+
+/// Identifies a valid symbol table, must match the kernel.
+const MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse ELF: {0}")]
+    Object(#[from] object::Error),
+
+    #[error("symbol '{0}' not found in ELF")]
+    SymbolNotFound(&'static str),
+
+    #[error("ELF has no .text section")]
+    NoTextSection,
+
+    #[error("symbol table is too large: {size} bytes, {capacity} bytes reserved")]
+    SymbolTableTooLarge { size: usize, capacity: usize },
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+struct Symbol {
+    address: u64,
+    size: u64,
+    name: String,
+}
+
+/// Append the function symbols of `elf` to `image`, at the region the linker script reserved.
+///
+/// Layout, all integers little endian:
+///
+/// - header: magic `KSYM` and the number of entries as `u32`
+/// - entries sorted by address: address `u64`, size `u32`, name offset `u32`, name length `u32`
+///   and a reserved `u32`
+/// - names, with offsets relative to the start of the table
+pub fn append_symbol_table(elf: &Path, image: &Path) -> Result<usize> {
+    let elf_data = fs::read(elf)?;
+    let file = object::File::parse(&*elf_data)?;
+
+    let find_symbol = |name: &'static str| {
+        file.symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address())
+            .ok_or(Error::SymbolNotFound(name))
+    };
+    let region_start = find_symbol(SYMBOLS_START)?;
+    let region_end = find_symbol(SYMBOLS_END)?;
+    // the binary image starts at the first loaded section, which is .text
+    let image_start = file
+        .section_by_name(".text")
+        .ok_or(Error::NoTextSection)?
+        .address();
+
+    let mut symbols = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            Some(Symbol {
+                address: symbol.address(),
+                size: symbol.size(),
+                name: format!("{:#}", rustc_demangle::demangle(name)),
+            })
+        })
+        .collect::<Vec<_>>();
+    sort_symbols(&mut symbols);
+
+    let table = encode(&symbols);
+    let capacity = (region_end - region_start) as usize;
+    if table.len() > capacity {
+        return Err(Error::SymbolTableTooLarge {
+            size: table.len(),
+            capacity,
+        });
+    }
+
+    let mut image_data = fs::read(image)?;
+    place_table(
+        &mut image_data,
+        (region_start - image_start) as usize,
+        &table,
+    );
+    fs::write(image, image_data)?;
+
+    Ok(symbols.len())
+}
+
+/// Sort `symbols` by address, keeping the first of those at the same address.
+fn sort_symbols(symbols: &mut Vec<Symbol>) {
+    symbols.sort_by_key(|symbol| symbol.address);
+    symbols.dedup_by_key(|symbol| symbol.address);
+}
+
+/// Put `table` at `offset` of `image`, which is padded with zeros or cut to end right before it.
+fn place_table(image: &mut Vec<u8>, offset: usize, table: &[u8]) {
+    image.resize(offset, 0);
+    image.extend_from_slice(table);
+}
+
+fn encode(symbols: &[Symbol]) -> Vec<u8> {
+    let names_start = HEADER_SIZE + symbols.len() * ENTRY_SIZE;
+
+    let mut table = Vec::with_capacity(names_start);
+    let mut names = Vec::new();
+
+    table.extend_from_slice(MAGIC);
+    table.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    for symbol in symbols {
+        let name_offset = names_start + names.len();
+        names.extend_from_slice(symbol.name.as_bytes());
+
+        table.extend_from_slice(&symbol.address.to_le_bytes());
+        table.extend_from_slice(&(symbol.size as u32).to_le_bytes());
+        table.extend_from_slice(&(name_offset as u32).to_le_bytes());
+        table.extend_from_slice(&(symbol.name.len() as u32).to_le_bytes());
+        table.extend_from_slice(&0u32.to_le_bytes());
+    }
+    table.extend_from_slice(&names);
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(address: u64, size: u64, name: &str) -> Symbol {
+        Symbol {
+            address,
+            size,
+            name: name.to_string(),
+        }
+    }
+
+    fn u32_at(table: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(table[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(table: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(table[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn encode_empty_table() {
+        let table = encode(&[]);
+        assert_eq!(table.len(), HEADER_SIZE);
+        assert_eq!(&table[..4], MAGIC);
+        assert_eq!(u32_at(&table, 4), 0);
+    }
+
+    #[test]
+    fn encode_entries_and_names() {
+        let symbols = [
+            symbol(0x1000, 0x20, "kernel_init"),
+            symbol(0x1020, 0x8, "main"),
+        ];
+        let table = encode(&symbols);
+
+        let names_start = HEADER_SIZE + 2 * ENTRY_SIZE;
+        assert_eq!(
+            table.len(),
+            names_start + "kernel_init".len() + "main".len()
+        );
+        assert_eq!(&table[..4], MAGIC);
+        assert_eq!(u32_at(&table, 4), 2);
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            let entry = HEADER_SIZE + i * ENTRY_SIZE;
+            assert_eq!(u64_at(&table, entry), symbol.address);
+            assert_eq!(u32_at(&table, entry + 8) as u64, symbol.size);
+            let name_offset = u32_at(&table, entry + 12) as usize;
+            let name_len = u32_at(&table, entry + 16) as usize;
+            assert_eq!(
+                &table[name_offset..name_offset + name_len],
+                symbol.name.as_bytes()
+            );
+            assert_eq!(u32_at(&table, entry + 20), 0);
+        }
+    }
+
+    #[test]
+    fn sort_symbols_by_address_without_duplicates() {
+        let mut symbols = vec![
+            symbol(0x2000, 4, "b"),
+            symbol(0x1000, 4, "a"),
+            symbol(0x2000, 4, "b_alias"),
+        ];
+        sort_symbols(&mut symbols);
+
+        let names: Vec<_> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn place_table_pads_the_image() {
+        let mut image = vec![1, 2, 3];
+        place_table(&mut image, 6, &[9, 9]);
+        assert_eq!(image, [1, 2, 3, 0, 0, 0, 9, 9]);
+    }
+
+    #[test]
+    fn place_table_cuts_the_image() {
+        let mut image = vec![1, 2, 3, 4];
+        place_table(&mut image, 2, &[9]);
+        assert_eq!(image, [1, 2, 9]);
+    }
+}